@@ -0,0 +1,272 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    progress::Progress,
+    scan::{Scan, Scanner},
+    similar,
+    sort::PathSorter,
+};
+
+type Digest = [u8; 32];
+
+/// Files found loose on disk which duplicate a file stored in an archive.
+pub struct Archived {
+    /// The archive members, each named as the archive's path followed by `!/` and the member's
+    /// path within it, e.g. `backup.zip!/photos/beach.jpg`. Members are never removed.
+    pub members: Vec<PathBuf>,
+
+    /// The archives holding the members.
+    pub archives: Vec<PathBuf>,
+
+    /// The loose copies, in order of preference.
+    pub files: Vec<PathBuf>,
+
+    /// The number of loose copies, from the front of `files`, which are to be kept.
+    pub keep: usize,
+
+    /// The length of each file.
+    pub len: u64,
+}
+
+impl Archived {
+    /// The loose copies to be kept. The preferred copy is kept, unless comparing against root
+    /// paths holding one of the archives.
+    pub fn keepers(&self) -> &[PathBuf] {
+        &self.files[..self.keep]
+    }
+
+    /// The loose copies which may be removed. When comparing, files in the root paths are kept.
+    pub fn duplicates(&self) -> &[PathBuf] {
+        &self.files[self.keep..]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl Kind {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Kind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Kind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Kind::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Kind::TarZst)
+        } else {
+            None
+        }
+    }
+}
+
+/// Find loose files duplicating members of zip and tar archives, and record them in the scan.
+///
+/// Members and loose files are compared by a digest of their full contents, and only where their
+/// lengths match. Archives which can't be read are skipped.
+pub fn scan(
+    scanner: &Scanner,
+    scan: &mut Scan,
+    sorter: &dyn PathSorter,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let files = similar::walk(scanner)?;
+    let keep_base = !scanner.compare.is_empty();
+
+    // Files in the root paths are never duplicates when comparing.
+    let (archives, loose): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|(_, path, _)| Kind::of(path).is_some());
+    let loose: Vec<_> = loose
+        .into_iter()
+        .filter(|(tier, _, meta)| meta.len > 0 && !(keep_base && *tier == 0))
+        .collect();
+
+    let lens: HashSet<u64> = loose.iter().map(|(_, _, meta)| meta.len).collect();
+    let bytes = archives.iter().map(|(_, _, meta)| meta.len).sum();
+    progress.candidates(archives.len(), bytes);
+
+    let mut members: HashMap<Digest, Vec<(PathBuf, &PathBuf, usize)>> = HashMap::new();
+    let mut member_lens = HashSet::new();
+    for (tier, path, meta) in &archives {
+        if let Ok(found) = read_members(path, &lens) {
+            for (name, len, digest) in found {
+                let mut member = OsString::from(path.as_os_str());
+                member.push("!/");
+                member.push(name);
+                members
+                    .entry(digest)
+                    .or_default()
+                    .push((member.into(), path, *tier));
+                member_lens.insert(len);
+            }
+        }
+        progress.hashed(meta.len);
+    }
+
+    let loose: Vec<_> = loose
+        .into_iter()
+        .filter(|(_, _, meta)| member_lens.contains(&meta.len))
+        .collect();
+    let bytes = loose.iter().map(|(_, _, meta)| meta.len).sum();
+    progress.candidates(loose.len(), bytes);
+
+    let mut copies: HashMap<Digest, Vec<PathBuf>> = HashMap::new();
+    for (_, path, meta) in loose {
+        // As with archives, a file which can't be read is passed over.
+        if let Ok(digest) = File::open(&path).and_then(digest)
+            && members.contains_key(&digest)
+        {
+            scan.cache.insert(path.clone(), meta.clone());
+            copies.entry(digest).or_default().push(path);
+        }
+        progress.hashed(meta.len);
+    }
+
+    for (digest, mut files) in copies {
+        let found = members.remove(&digest).unwrap_or_default();
+        let in_base = found.iter().any(|&(_, _, tier)| tier == 0);
+        let (mut members, mut archives): (Vec<_>, Vec<_>) = found
+            .into_iter()
+            .map(|(member, archive, _)| (member, archive.clone()))
+            .unzip();
+        members.sort();
+        archives.sort();
+        archives.dedup();
+        sorter.sort(&mut files, &scan.cache);
+
+        let len = scan.cache.get(&files[0]).map_or(0, |meta| meta.len);
+        scan.archived.push(Archived {
+            members,
+            archives,
+            files,
+            keep: usize::from(!(keep_base && in_base)),
+            len,
+        });
+    }
+
+    scan.archived.sort_by(|a, b| a.members.cmp(&b.members));
+    Ok(())
+}
+
+/// Read the name, length and digest of each member of an archive whose length is among `lens`.
+fn read_members(path: &Path, lens: &HashSet<u64>) -> io::Result<Vec<(String, u64, Digest)>> {
+    let file = File::open(path)?;
+    match Kind::of(path) {
+        Some(Kind::Zip) => read_zip(file, lens),
+        Some(Kind::Tar) => read_tar(BufReader::new(file), lens),
+        Some(Kind::TarGz) => read_tar(flate2::read::GzDecoder::new(BufReader::new(file)), lens),
+        Some(Kind::TarZst) => read_tar(zstd::Decoder::new(file)?, lens),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn read_zip(file: File, lens: &HashSet<u64>) -> io::Result<Vec<(String, u64, Digest)>> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(io::Error::other)?;
+    let mut members = Vec::new();
+
+    for idx in 0..archive.len() {
+        let member = archive.by_index(idx).map_err(io::Error::other)?;
+        if !member.is_file() || !lens.contains(&member.size()) {
+            continue;
+        }
+
+        let name = member.name().to_owned();
+        let len = member.size();
+        members.push((name, len, digest(member)?));
+    }
+
+    Ok(members)
+}
+
+fn read_tar(reader: impl Read, lens: &HashSet<u64>) -> io::Result<Vec<(String, u64, Digest)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() || !lens.contains(&entry.size()) {
+            continue;
+        }
+
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let len = entry.size();
+        members.push((name, len, digest(entry)?));
+    }
+
+    Ok(members)
+}
+
+fn digest(mut reader: impl Read) -> io::Result<Digest> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(*hasher.finalize().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use crate::{plan::Action, scan::Scanner, testing::TempDir};
+
+    #[test]
+    fn loose_copies_of_members_are_duplicates() {
+        let root = TempDir::new("archives");
+        fs::write(root.join("beach.jpg"), "sand and sea").unwrap();
+        fs::write(root.join("copy.jpg"), "sand and sea").unwrap();
+        fs::write(root.join("other.jpg"), "not archived").unwrap();
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(root.join("backup.zip")).unwrap());
+        zip.start_file("photos/beach.jpg", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"sand and sea").unwrap();
+        zip.finish().unwrap();
+
+        let mut tar = tar::Builder::new(fs::File::create(root.join("backup.tar")).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(12);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "beach.jpg", &b"sand and sea"[..])
+            .unwrap();
+        tar.finish().unwrap();
+
+        let scan = Scanner::new(&*root).archives(true).scan().unwrap();
+        let plan = scan.plan();
+
+        assert_eq!(scan.archived.len(), 1);
+        let archived = &scan.archived[0];
+        assert_eq!(
+            archived.members,
+            [
+                root.join("backup.tar!/beach.jpg"),
+                root.join("backup.zip!/photos/beach.jpg"),
+            ]
+        );
+        assert_eq!(
+            archived.files,
+            [root.join("beach.jpg"), root.join("copy.jpg")]
+        );
+
+        let removed: Vec<_> = plan
+            .actions
+            .iter()
+            .map(|action| match action {
+                Action::Remove { path, .. } | Action::RemoveDirectory { path } => path.clone(),
+            })
+            .collect();
+        assert_eq!(removed, [root.join("copy.jpg")]);
+    }
+}
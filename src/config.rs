@@ -1,88 +1,536 @@
-use std::{error::Error, fmt::Display, str::FromStr};
+use std::{io, path::PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use yogi::{GroupOrder, ImageHash, PathRanker, Scanner, SortOrder, TextHash, get_sorter_with};
+
+use crate::settings::Settings;
 
 /// Examine a directory for duplicated files and remove them.
 #[derive(Clone, Debug, Parser)]
 #[command(version)]
 pub struct Args {
-    /// The root path to be examined
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The root paths to be examined
     /// Defaults to "."
-    path: Option<String>,
+    paths: Vec<String>,
 
     /// Additional paths (files in root path will be preferred)
     #[arg(short, long)]
     pub compare: Vec<String>,
 
+    /// Rank root paths in the order given, always keeping files from earlier paths
+    ///
+    /// Within a single root path, files to keep are chosen according to --sort. Without this,
+    /// all root paths are examined as though they were a single tree.
+    #[arg(long, conflicts_with = "compare", overrides_with = "no_priority_order")]
+    pub priority_order: bool,
+
+    /// Examine all root paths as a single tree, even if a config file sets --priority-order
+    #[arg(long, overrides_with = "priority_order")]
+    no_priority_order: bool,
+
+    /// Also treat duplicates within the compare paths as duplicates (keeping one of each)
+    #[arg(long, overrides_with = "no_dedupe_compare")]
+    pub dedupe_compare: bool,
+
+    /// Keep every duplicate within the compare paths, even if a config file sets
+    /// --dedupe-compare
+    #[arg(long, overrides_with = "dedupe_compare")]
+    no_dedupe_compare: bool,
+
     /// Remove duplicate files.
+    ///
+    /// To review what will be removed first, save a plan with 'yogi scan --save' and remove the
+    /// files with 'yogi apply' instead.
     #[arg(short, long)]
     pub force: bool,
 
+    /// After removing duplicates, remove any directories left empty
+    ///
+    /// Directories which were already empty, or which still hold hidden or ignored files, are
+    /// left alone, as are the root paths themselves. Requires --force, except with 'yogi apply'.
+    #[arg(long, global = true)]
+    pub prune_empty_dirs: bool,
+
+    /// Order groups of duplicates by 'size' (largest files first), 'count' (most copies first),
+    /// 'wasted' (most space to reclaim first) or 'path' (of the file to be kept)
+    ///
+    /// Applies to every report format. Groups which tie are ordered by path. Defaults to 'path'.
+    #[arg(long, value_name = "ORDER")]
+    pub order_groups: Option<GroupOrder>,
+
+    /// Only report, or remove, the first N groups of duplicates, once ordered
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Also print statistics: files scanned, time spent, the largest groups and the directories
+    /// with the most to reclaim
+    #[arg(long)]
+    pub stats: bool,
+
+    /// How to print the report: 'text', 'json' or 'html'
+    ///
+    /// The HTML report is a single page, with sortable tables and thumbnails of images, which
+    /// can be opened in any browser; write it to a file with --output. Defaults to 'text'.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        conflicts_with_all = ["force", "explain", "directories", "empty_dirs"]
+    )]
+    pub format: Option<Format>,
+
+    /// Write the JSON or HTML report to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Also look for files which are similar without being identical
+    ///
+    /// 'images' finds the same picture resized or re-encoded (JPEG, PNG, WebP or GIF). 'text'
+    /// finds documents sharing most of their wording, and 'text-exact' finds documents differing
+    /// only in whitespace and line endings. Similar files are listed apart from exact duplicates
+    /// and are only removed with --remove-similar.
+    #[arg(long, value_name = "KIND")]
+    pub similar: Option<SimilarKind>,
+
+    /// List directories with nothing in them, instead of duplicates
+    ///
+    /// Useful after removing duplicates. Directories holding only empty directories are empty
+    /// too; hidden files count as content. With --force, empty directories are removed.
+    #[arg(long, conflicts_with_all = ["directories", "similar", "scan_archives", "explain"])]
+    pub empty_dirs: bool,
+
+    /// Also look inside zip and tar archives (.zip, .tar, .tar.gz, .tar.zst) for files duplicated
+    /// loose on disk
+    ///
+    /// Archive members are listed as "archive.zip!/inner/path". Archives are never modified; with
+    /// --force, loose copies besides the preferred one are removed.
+    #[arg(long, conflicts_with = "directories")]
+    pub scan_archives: bool,
+
+    /// Find directories with identical contents, instead of individual files
+    ///
+    /// Directories match when they hold the same file names with the same contents, arranged in
+    /// the same way. Where a whole tree is copied, only its topmost directory is listed. With
+    /// --force, the files in each duplicate directory are removed, then the directory itself if
+    /// nothing else is left in it.
+    #[arg(long, conflicts_with_all = ["similar", "explain"])]
+    pub directories: bool,
+
+    /// Also list directories whose files are all found in another directory
+    ///
+    /// Such directories are never removed. Finding them means reading every file.
+    #[arg(long, requires = "directories")]
+    pub subsets: bool,
+
+    /// How to hash images: 'average', 'difference' or 'perceptual'
+    #[arg(long, value_name = "HASH", default_value = "perceptual")]
+    pub image_hash: ImageHash,
+
+    /// The number of bits, out of 64, by which the hashes of similar files may differ
+    ///
+    /// Defaults to 10 for images and 6 for text.
+    #[arg(long, value_name = "BITS")]
+    pub max_distance: Option<u32>,
+
+    /// Remove similar files along with exact duplicates
+    #[arg(long, requires_all = ["force", "similar"])]
+    pub remove_similar: bool,
+
+    /// Show why each kept file was preferred over the next best
+    ///
+    /// Lists what each sort rule saw in the two files, up to the rule which decided between them.
+    #[arg(long, conflicts_with = "force")]
+    pub explain: bool,
+
     /// Keep 'oldest' or 'newest' files instead of 'most descriptive.'
     ///
-    /// Note that this only applies to the single tree process.
-    #[arg(short, long)]
-    pub sort: Option<SortOrder>,
+    /// 'oldest' and 'newest' go by creation date, which not every file system records; use
+    /// 'modified-oldest', 'modified-newest', 'accessed-oldest' or 'accessed-newest' to go by
+    /// another timestamp. Files without the timestamp are never kept over files with one. Also
+    /// available are 'shortest-path', 'deepest-path' and 'alphabetical'.
+    ///
+    /// Several orders may be given, separated by commas; each one breaks ties left by those
+    /// before it. Use 'prefer:<path>' to keep files beneath a given directory, e.g.
+    /// "prefer:/photos/originals,descriptive,oldest".
+    ///
+    /// When comparing, files in the root path are always kept; this decides which of them is
+    /// listed first, and which compare file is kept when deduplicating the compare paths.
+    #[arg(short, long, value_delimiter(','))]
+    pub sort: Vec<SortOrder>,
+
+    /// Keep files beneath this directory (may be repeated; earlier directories win)
+    ///
+    /// Takes precedence over --sort, which breaks ties among files in the same directory.
+    #[arg(long)]
+    pub prefer: Vec<String>,
+
+    /// Treat files beneath this directory as the copies to remove (may be repeated)
+    #[arg(long)]
+    pub avoid: Vec<String>,
+
+    /// Add the words in this file to the dictionary used by the 'descriptive' sort order (may be
+    /// repeated)
+    ///
+    /// Words are separated by whitespace, typically one per line; case does not matter. Useful for
+    /// names in other languages, project names and the like.
+    #[arg(long, value_name = "FILE")]
+    pub dictionary: Vec<String>,
+
+    /// Do not use the built-in English dictionary, only those given by --dictionary
+    #[arg(long, overrides_with = "default_dictionary")]
+    pub no_default_dictionary: bool,
+
+    /// Use the built-in English dictionary, even if a config file sets --no-default-dictionary
+    #[arg(long, overrides_with = "no_default_dictionary")]
+    default_dictionary: bool,
+
+    /// Treat files whose names match this regex as copies (may be repeated)
+    ///
+    /// Give a weight as "WEIGHT:REGEX"; the default is 1. Copy markers like "(1)", "- Copy" and
+    /// ".bak" are recognized without this, with weights of 1 or 2. Among otherwise equally
+    /// descriptive paths, the one with the least total weight is kept.
+    #[arg(long, value_name = "[WEIGHT:]REGEX")]
+    pub penalty: Vec<String>,
+
+    /// Also examine empty files, which are otherwise skipped
+    ///
+    /// Every empty file is a duplicate of every other, and empty files are often kept on purpose,
+    /// as markers.
+    #[arg(long, overrides_with = "no_include_empty")]
+    pub include_empty: bool,
+
+    /// Skip empty files, even if a config file sets --include-empty
+    #[arg(long, overrides_with = "include_empty")]
+    no_include_empty: bool,
 
     /// Do not recurse into subdirectories (applies to root path)
-    #[arg(short, long)]
+    #[arg(short, long, overrides_with = "recurse")]
     pub no_recurse: bool,
 
+    /// Recurse into subdirectories, even if a config file sets --no-recurse
+    #[arg(long, overrides_with = "no_recurse")]
+    recurse: bool,
+
     /// Ignore these directories
     #[arg(short, long, value_delimiter(','))]
     pub ignore: Vec<String>,
+
+    /// How to display paths: 'absolute', 'relative' (to the root path in which each file was
+    /// found), or 'name' (the file name alone)
+    ///
+    /// By default, paths are displayed as found, or relative when comparing.
+    #[arg(long = "paths", value_name = "STYLE")]
+    pub path_style: Option<PathStyle>,
+
+    /// Do not display scan progress on stderr
+    ///
+    /// Progress is never displayed when stderr is not a terminal.
+    #[arg(short, long, overrides_with = "no_quiet")]
+    pub quiet: bool,
+
+    /// Display scan progress, even if a config file sets --quiet
+    #[arg(long, overrides_with = "quiet")]
+    no_quiet: bool,
+
+    /// Apply a named profile from the config file
+    ///
+    /// Settings are read from ~/.config/yogi/config.toml and then ./.yogi.toml; profiles are
+    /// defined in either under [profiles.<name>]. Flags given here override both.
+    #[arg(long, env = "YOGI_PROFILE")]
+    pub profile: Option<String>,
 }
 
-#[derive(Copy, Clone, Debug, Default)]
-pub enum SortOrder {
-    #[default]
-    Descriptive,
-    Newest,
-    Oldest,
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Inspect settings from config files
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Scan for duplicates, as when no command is given, optionally saving a plan for review
+    Scan {
+        /// Save the files to keep and remove to this file, to be reviewed and then applied with
+        /// 'yogi apply', instead of printing a report
+        #[arg(long, value_name = "FILE")]
+        save: Option<PathBuf>,
+
+        /// The root paths to be examined, in place of those given before the subcommand
+        paths: Vec<String>,
+    },
+
+    /// Remove the duplicates listed in a saved plan
+    ///
+    /// Each file is checked first: any file whose length, modification time or contents have
+    /// changed since the scan is refused, as is every file in a group with no unchanged file
+    /// left to keep.
+    Apply {
+        /// A plan saved by 'yogi scan --save'
+        plan: PathBuf,
+    },
+
+    /// Summarize duplicates in other ways
+    #[command(subcommand)]
+    Report(ReportCommand),
 }
 
-impl FromStr for SortOrder {
-    type Err = ParseSortOrderError;
+#[derive(Clone, Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective settings: config files, then profile, then flags
+    Show,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_ref() {
-            "d" | "descriptive" => Ok(SortOrder::Descriptive),
-            "o" | "oldest" => Ok(SortOrder::Oldest),
-            "n" | "newest" => Ok(SortOrder::Newest),
-            _ => Err(ParseSortOrderError(s.into())),
-        }
-    }
+#[derive(Clone, Debug, Subcommand)]
+pub enum ReportCommand {
+    /// Show which directories hold copies of one another's files, and how much each pair shares
+    ///
+    /// Nothing is removed; use this to decide which directory is a stale copy of another.
+    Dirs {
+        /// The root paths to be examined, in place of those given before the subcommand
+        paths: Vec<String>,
+    },
 }
 
-#[derive(Clone, Debug)]
-pub struct ParseSortOrderError(String);
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Text,
+    Json,
+    Html,
+}
 
-impl Display for ParseSortOrderError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{:?} is not a valid sort order\nTry one of descriptive, oldest, newest",
-            self.0
-        )
-    }
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SimilarKind {
+    Images,
+    Text,
+    TextExact,
 }
 
-impl Error for ParseSortOrderError {}
+#[derive(Copy, Clone, Debug, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStyle {
+    #[value(skip)]
+    #[serde(skip)]
+    AsFound,
+    Absolute,
+    Relative,
+    Name,
+}
 
 impl Args {
     pub fn parse() -> Self {
         Parser::parse()
     }
 
-    pub fn path(&self) -> &str {
-        self.path.as_deref().unwrap_or(".")
+    /// Fill in anything not given on the command line from config files.
+    pub fn configure(mut self) -> io::Result<Self> {
+        fn list<T>(args: &mut Vec<T>, settings: Vec<T>) {
+            if args.is_empty() {
+                *args = settings;
+            }
+        }
+
+        // A flag may be given, negated, or left to the settings.
+        fn flag(arg: &mut bool, negated: bool, setting: Option<bool>) {
+            if !*arg && !negated {
+                *arg = setting.unwrap_or_default();
+            }
+        }
+
+        let mut settings = Settings::load(self.profile.as_deref())?;
+        settings.resolve_beneath(&self.paths());
+        let sort = settings
+            .sort
+            .iter()
+            .map(|order| order.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        list(&mut self.compare, settings.compare);
+        list(&mut self.ignore, settings.ignore);
+        list(&mut self.sort, sort);
+        list(&mut self.prefer, settings.prefer);
+        list(&mut self.avoid, settings.avoid);
+        list(&mut self.dictionary, settings.dictionary);
+        list(&mut self.penalty, settings.penalty);
+        flag(&mut self.no_recurse, self.recurse, settings.no_recurse);
+        flag(
+            &mut self.priority_order,
+            self.no_priority_order,
+            settings.priority_order,
+        );
+        flag(
+            &mut self.dedupe_compare,
+            self.no_dedupe_compare,
+            settings.dedupe_compare,
+        );
+        flag(
+            &mut self.no_default_dictionary,
+            self.default_dictionary,
+            settings.no_default_dictionary,
+        );
+        flag(
+            &mut self.include_empty,
+            self.no_include_empty,
+            settings.include_empty,
+        );
+        flag(&mut self.quiet, self.no_quiet, settings.quiet);
+        self.path_style = self.path_style.or(settings.paths);
+
+        if self.order_groups.is_none()
+            && let Some(order) = settings.order_groups
+        {
+            let order = order
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            self.order_groups = Some(order);
+        }
+
+        // Only the text report is available when removing files or listing directories.
+        let text_only = self.force || self.explain || self.directories || self.empty_dirs;
+        if !text_only {
+            self.format = self.format.or(settings.format);
+        }
+
+        Ok(self)
     }
 
-    pub fn sort_order(&self) -> SortOrder {
-        self.sort.unwrap_or_default()
+    /// The settings in effect, in the form they would take in a config file.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            compare: self.compare.clone(),
+            ignore: self.ignore.clone(),
+            sort: self.sort.iter().map(ToString::to_string).collect(),
+            prefer: self.prefer.clone(),
+            avoid: self.avoid.clone(),
+            dictionary: self.dictionary.clone(),
+            no_default_dictionary: Some(self.no_default_dictionary),
+            penalty: self.penalty.clone(),
+            no_recurse: Some(self.no_recurse),
+            priority_order: Some(self.priority_order),
+            dedupe_compare: Some(self.dedupe_compare),
+            include_empty: Some(self.include_empty),
+            quiet: Some(self.quiet),
+            paths: self.path_style,
+            format: Some(self.format()),
+            order_groups: Some(self.order_groups().to_string()),
+            profiles: Default::default(),
+        }
+    }
+
+    pub fn paths(&self) -> Vec<&str> {
+        if let Some(Command::Report(ReportCommand::Dirs { paths }) | Command::Scan { paths, .. }) =
+            &self.command
+            && !paths.is_empty()
+        {
+            paths.iter().map(AsRef::as_ref).collect()
+        } else if self.paths.is_empty() {
+            vec!["."]
+        } else {
+            self.paths.iter().map(AsRef::as_ref).collect()
+        }
+    }
+
+    pub fn sort_order(&self) -> Vec<SortOrder> {
+        let prefer = self.prefer.iter().map(|dir| SortOrder::Prefer(dir.into()));
+        let avoid = self.avoid.iter().map(|dir| SortOrder::Avoid(dir.into()));
+        prefer
+            .chain(avoid)
+            .chain(self.sort.iter().cloned())
+            .collect()
+    }
+
+    pub fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Text)
+    }
+
+    pub fn order_groups(&self) -> GroupOrder {
+        self.order_groups.unwrap_or_default()
+    }
+
+    pub fn path_style(&self) -> PathStyle {
+        match self.path_style {
+            Some(style) => style,
+            None if self.compare.is_empty() => PathStyle::AsFound,
+            None => PathStyle::Relative,
+        }
     }
 
     pub fn recurse(&self) -> bool {
         !self.no_recurse
     }
+
+    /// The ranker for the descriptive sort order, with any extra dictionaries and penalties. It
+    /// is built once, loading each word list, and shared by every rule which needs it.
+    pub fn ranker(&self) -> io::Result<PathRanker> {
+        let ranker = if self.no_default_dictionary {
+            PathRanker::empty()
+        } else {
+            PathRanker::new()
+        };
+
+        let ranker = self.dictionary.iter().try_fold(ranker, |ranker, path| {
+            ranker.dictionary(path).map_err(|e| {
+                io::Error::new(e.kind(), format!("unable to read dictionary {path}: {e}"))
+            })
+        })?;
+
+        self.penalty.iter().try_fold(ranker, |ranker, penalty| {
+            let (weight, pattern) = penalty
+                .split_once(':')
+                .and_then(|(weight, pattern)| Some((weight.parse().ok()?, pattern)))
+                .unwrap_or((1, penalty));
+
+            ranker.penalize(pattern, weight).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("bad penalty: {e}"))
+            })
+        })
+    }
+
+    pub fn scanner(&self) -> io::Result<Scanner> {
+        let paths = self.paths();
+        let sorter = get_sorter_with(&self.sort_order(), &self.ranker()?);
+        let mut scanner = Scanner::new(paths[0])
+            .recurse(self.recurse())
+            .sorter(Box::new(sorter));
+
+        scanner = match self.similar {
+            Some(SimilarKind::Images) => {
+                scanner.similar_images(self.image_hash, self.max_distance.unwrap_or(10))
+            }
+            Some(SimilarKind::Text) => {
+                scanner.similar_text(TextHash::SimHash, self.max_distance.unwrap_or(6))
+            }
+            Some(SimilarKind::TextExact) => scanner.similar_text(TextHash::Normalized, 0),
+            None => scanner,
+        };
+
+        scanner = scanner
+            .subsets(self.subsets)
+            .archives(self.scan_archives)
+            .include_empty(self.include_empty)
+            .order_groups(self.order_groups())
+            .priority_order(self.priority_order)
+            .dedupe_compare(self.dedupe_compare)
+            .progress(!self.quiet);
+
+        if let Some(top) = self.top {
+            scanner = scanner.top(top);
+        }
+
+        for path in &paths[1..] {
+            scanner = scanner.root(*path);
+        }
+
+        for path in &self.compare {
+            scanner = scanner.compare(path);
+        }
+
+        for path in &self.ignore {
+            scanner = scanner.ignore(path);
+        }
+
+        Ok(scanner)
+    }
 }
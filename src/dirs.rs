@@ -0,0 +1,588 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::{HashMap, HashSet};
+use imprint::Imprint;
+
+use crate::{
+    meta::Metacache, multiple::is_conflict, plan::Plan, progress::Progress, scan::Scanner,
+    sort::PathSorter, stats::Stats,
+};
+
+type Digest = [u8; 32];
+
+/// The result of a directory scan.
+pub struct DirectoryScan {
+    /// Groups of directories with identical contents. Where whole trees are duplicated, only the
+    /// topmost directories are listed, and not each of the directories beneath them.
+    pub groups: Vec<DirectoryGroup>,
+
+    /// Directories whose every file can be found, at the same relative path, in another
+    /// directory holding more. Only found when asked for.
+    pub subsets: Vec<Subset>,
+
+    /// The roots examined by the scan, grouped by priority, highest first.
+    pub tiers: Vec<Vec<PathBuf>>,
+
+    /// Figures gathered while scanning.
+    pub stats: Stats,
+
+    /// The files and subdirectories beneath each directory to be removed.
+    pub(crate) contents: HashMap<PathBuf, Contents>,
+}
+
+impl DirectoryScan {
+    /// The root under which a directory was found. Where roots overlap, the directory belongs to
+    /// the first.
+    pub fn root_of(&self, path: &Path) -> Option<&Path> {
+        self.tiers
+            .iter()
+            .flatten()
+            .find(|root| path.starts_with(root))
+            .map(AsRef::as_ref)
+    }
+
+    /// Plan the removal of every duplicate directory.
+    pub fn plan(&self) -> Plan {
+        Plan::for_directories(self)
+    }
+}
+
+/// A set of directories with identical contents: the same file names, holding the same data,
+/// arranged in the same way.
+pub struct DirectoryGroup {
+    /// The directories in this group, in order of preference.
+    pub dirs: Vec<PathBuf>,
+
+    /// The number of directories, from the front of `dirs`, which are to be kept.
+    pub keep: usize,
+
+    /// The number of files in each directory, including those in subdirectories.
+    pub files: usize,
+
+    /// The combined length of the files in each directory.
+    pub len: u64,
+}
+
+impl DirectoryGroup {
+    /// The directories to be kept.
+    pub fn keepers(&self) -> &[PathBuf] {
+        &self.dirs[..self.keep]
+    }
+
+    /// The directories which duplicate a keeper.
+    pub fn duplicates(&self) -> &[PathBuf] {
+        &self.dirs[self.keep..]
+    }
+}
+
+/// A directory whose contents are all found in another directory.
+#[derive(Clone, Debug)]
+pub struct Subset {
+    /// The directory whose contents are all found elsewhere.
+    pub dir: PathBuf,
+
+    /// The directory containing everything in `dir`, and more.
+    pub of: PathBuf,
+}
+
+/// What lies beneath a directory: every file, with its length, and every subdirectory, deepest
+/// first.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Contents {
+    pub files: Vec<(PathBuf, u64)>,
+    pub dirs: Vec<PathBuf>,
+}
+
+struct File {
+    path: PathBuf,
+    dir: usize,
+    len: u64,
+    content: Option<Digest>,
+}
+
+struct Dir {
+    path: PathBuf,
+    tier: usize,
+    parent: Option<usize>,
+    depth: usize,
+    files: Vec<usize>,
+    subdirs: Vec<usize>,
+}
+
+/// Everything found by walking the roots, arranged as a tree of directories.
+#[derive(Default)]
+struct Tree {
+    files: Vec<File>,
+    dirs: Vec<Dir>,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl Tree {
+    /// Find or create a directory, along with any of its ancestors up to the root.
+    fn dir(&mut self, path: &Path, root: &Path, tier: usize) -> usize {
+        if let Some(&idx) = self.index.get(path) {
+            return idx;
+        }
+
+        let parent = path
+            .parent()
+            .filter(|_| path != root)
+            .map(|parent| self.dir(parent, root, tier));
+
+        let idx = self.dirs.len();
+        self.dirs.push(Dir {
+            path: path.to_owned(),
+            tier,
+            parent,
+            depth: path.components().count(),
+            files: Vec::new(),
+            subdirs: Vec::new(),
+        });
+        self.index.insert(path.to_owned(), idx);
+
+        if let Some(parent) = parent {
+            self.dirs[parent].subdirs.push(idx);
+        }
+        idx
+    }
+
+    /// Directory indices, deepest first, so that each directory comes before its parent.
+    fn bottom_up(&self) -> Vec<usize> {
+        let mut order: Vec<_> = (0..self.dirs.len()).collect();
+        order.sort_by_key(|&idx| std::cmp::Reverse(self.dirs[idx].depth));
+        order
+    }
+
+    /// Every file beneath each directory, at any depth.
+    fn all_files(&self) -> Vec<Vec<usize>> {
+        let mut all = vec![Vec::new(); self.dirs.len()];
+        for (idx, file) in self.files.iter().enumerate() {
+            let mut dir = Some(file.dir);
+            while let Some(current) = dir {
+                all[current].push(idx);
+                dir = self.dirs[current].parent;
+            }
+        }
+        all
+    }
+
+    /// Compute a Merkle-style digest of each directory from the names of its entries, the digest
+    /// of each file as given by `leaf`, and the digest of each subdirectory.
+    fn digests(&self, leaf: impl Fn(&File) -> Digest) -> Vec<Digest> {
+        let mut digests = vec![[0; 32]; self.dirs.len()];
+        for idx in self.bottom_up() {
+            let dir = &self.dirs[idx];
+            let files = dir.files.iter().map(|&file| {
+                let file = &self.files[file];
+                (name(&file.path), b'f', leaf(file))
+            });
+            let subdirs = dir
+                .subdirs
+                .iter()
+                .map(|&subdir| (name(&self.dirs[subdir].path), b'd', digests[subdir]));
+
+            let mut entries: Vec<_> = files.chain(subdirs).collect();
+            entries.sort_unstable();
+
+            let mut hasher = blake3::Hasher::new();
+            for (name, kind, digest) in entries {
+                hasher.update(&(name.len() as u64).to_le_bytes());
+                hasher.update(name.as_encoded_bytes());
+                hasher.update(&[kind]);
+                hasher.update(&digest);
+            }
+            digests[idx] = *hasher.finalize().as_bytes();
+        }
+        digests
+    }
+}
+
+fn name(path: &Path) -> &OsStr {
+    path.file_name().unwrap_or(path.as_os_str())
+}
+
+pub fn scan(
+    scanner: &Scanner,
+    sorter: &dyn PathSorter,
+    progress: &mut Progress,
+) -> io::Result<DirectoryScan> {
+    let tiers = scanner.tiers();
+    let mut tree = walk(scanner, &tiers, progress)?;
+
+    // Directories can only be identical if they look alike, with the same names and lengths
+    // throughout, so only the files beneath those need to be read. Finding subsets means reading
+    // everything.
+    let shapes = tree.digests(|file| *blake3::hash(&file.len.to_le_bytes()).as_bytes());
+    let mut candidates = vec![scanner.subsets; tree.dirs.len()];
+    if !scanner.subsets {
+        let mut counts: HashMap<Digest, usize> = HashMap::new();
+        for shape in &shapes {
+            *counts.entry(*shape).or_default() += 1;
+        }
+
+        let mut top_down = tree.bottom_up();
+        top_down.reverse();
+        for idx in top_down {
+            candidates[idx] = counts[&shapes[idx]] > 1
+                || tree.dirs[idx]
+                    .parent
+                    .is_some_and(|parent| candidates[parent]);
+        }
+    }
+
+    let to_hash: Vec<_> = (0..tree.files.len())
+        .filter(|&idx| candidates[tree.files[idx].dir])
+        .collect();
+    let bytes = to_hash.iter().map(|&idx| tree.files[idx].len).sum();
+    progress.candidates(to_hash.len(), bytes);
+
+    for idx in to_hash {
+        let file = &mut tree.files[idx];
+        let imprint = Imprint::new(&file.path)?;
+        file.content = Some(*blake3::hash(imprint.to_string().as_bytes()).as_bytes());
+        progress.hashed(file.len);
+    }
+
+    let digests = tree.digests(|file| {
+        file.content
+            .unwrap_or_else(|| *blake3::hash(&file.len.to_le_bytes()).as_bytes())
+    });
+
+    let all_files = tree.all_files();
+    let groups = find_groups(scanner, sorter, &tree, &digests, &candidates, &all_files)?;
+    let subsets = if scanner.subsets {
+        find_subsets(&tree, &all_files)
+    } else {
+        Vec::new()
+    };
+
+    let bottom_up = tree.bottom_up();
+    let mut contents = HashMap::new();
+    for dir in groups.iter().flat_map(|group| group.duplicates()) {
+        let idx = tree.index[dir];
+        let files = all_files[idx]
+            .iter()
+            .map(|&file| (tree.files[file].path.clone(), tree.files[file].len))
+            .collect();
+
+        let dirs = bottom_up
+            .iter()
+            .map(|&sub| &tree.dirs[sub].path)
+            .filter(|path| path.starts_with(dir))
+            .cloned()
+            .collect();
+        contents.insert(dir.clone(), Contents { files, dirs });
+    }
+
+    Ok(DirectoryScan {
+        groups,
+        subsets,
+        tiers,
+        stats: Stats::default(),
+        contents,
+    })
+}
+
+fn walk(scanner: &Scanner, tiers: &[Vec<PathBuf>], progress: &mut Progress) -> io::Result<Tree> {
+    let mut tree = Tree::default();
+
+    // As with files, a directory reachable from more than one root belongs to the first.
+    let mut seen = HashSet::new();
+    let overlapping = tiers.iter().map(Vec::len).sum::<usize>() > 1;
+
+    for (tier, roots) in tiers.iter().enumerate() {
+        for root in roots {
+            for entry in crate::list_entries(root, scanner.recurse, &scanner.ignore) {
+                progress.walked();
+                let path = entry.path();
+                if overlapping && !crate::canonical(path).is_some_and(|path| seen.insert(path)) {
+                    continue;
+                }
+
+                // A root which is itself a file has no directory to speak of.
+                let Some(parent) = path.parent().filter(|parent| parent.starts_with(root)) else {
+                    continue;
+                };
+
+                let Some(meta) = crate::metadata(path) else {
+                    continue;
+                };
+                if !scanner.accepts(path, &meta) {
+                    continue;
+                }
+
+                progress.examined(meta.len);
+                let dir = tree.dir(parent, root, tier);
+                tree.dirs[dir].files.push(tree.files.len());
+                tree.files.push(File {
+                    path: path.to_owned(),
+                    dir,
+                    len: meta.len,
+                    content: None,
+                });
+            }
+        }
+    }
+
+    Ok(tree)
+}
+
+fn find_groups(
+    scanner: &Scanner,
+    sorter: &dyn PathSorter,
+    tree: &Tree,
+    digests: &[Digest],
+    candidates: &[bool],
+    all_files: &[Vec<usize>],
+) -> io::Result<Vec<DirectoryGroup>> {
+    let mut by_digest: HashMap<Digest, Vec<usize>> = HashMap::new();
+    for (idx, digest) in digests.iter().enumerate() {
+        if candidates[idx] && !all_files[idx].is_empty() {
+            by_digest.entry(*digest).or_default().push(idx);
+        }
+    }
+
+    let duplicated: HashSet<usize> = by_digest
+        .values()
+        .filter(|dirs| dirs.len() > 1)
+        .flatten()
+        .copied()
+        .collect();
+
+    // A group is only worth reporting if its directories aren't all accounted for by a single
+    // group of their parents: a group of distinct parents, each holding one of them.
+    let tier_count = scanner.tiers().len();
+    let keep_base = !scanner.compare.is_empty();
+    let mut groups = Vec::new();
+
+    for dirs in by_digest.into_values().filter(|dirs| dirs.len() > 1) {
+        let parents: Option<HashSet<usize>> =
+            dirs.iter().map(|&idx| tree.dirs[idx].parent).collect();
+        let implied = parents.is_some_and(|parents| {
+            let digest = parents.iter().next().map(|&parent| digests[parent]);
+            parents.len() == dirs.len()
+                && parents
+                    .iter()
+                    .all(|parent| duplicated.contains(parent) && Some(digests[*parent]) == digest)
+        });
+        if implied {
+            continue;
+        }
+
+        let mut tiers = vec![Vec::new(); tier_count];
+        for &idx in &dirs {
+            tiers[tree.dirs[idx].tier].push(tree.dirs[idx].path.clone());
+        }
+
+        let base = tiers[0].len();
+        if !is_conflict(keep_base, scanner.dedupe_compare, base, dirs.len() - base) {
+            continue;
+        }
+
+        let mut cache = Metacache::new();
+        for &idx in &dirs {
+            let path = &tree.dirs[idx].path;
+            cache.insert(path.clone(), path.metadata()?.into());
+        }
+
+        let files = &all_files[dirs[0]];
+        groups.push(DirectoryGroup {
+            dirs: tiers
+                .into_iter()
+                .flat_map(|mut dirs| {
+                    sorter.sort(&mut dirs, &cache);
+                    dirs
+                })
+                .collect(),
+            keep: if keep_base { base.max(1) } else { 1 },
+            files: files.len(),
+            len: files.iter().map(|&file| tree.files[file].len).sum(),
+        });
+    }
+
+    groups.sort_by(|a, b| a.dirs.cmp(&b.dirs));
+    Ok(groups)
+}
+
+fn find_subsets(tree: &Tree, all_files: &[Vec<usize>]) -> Vec<Subset> {
+    let mut by_content: HashMap<(&OsStr, Digest), Vec<usize>> = HashMap::new();
+    for (idx, file) in tree.files.iter().enumerate() {
+        if let Some(content) = file.content {
+            by_content
+                .entry((name(&file.path), content))
+                .or_default()
+                .push(idx);
+        }
+    }
+
+    let file_index: HashMap<&Path, usize> = tree
+        .files
+        .iter()
+        .enumerate()
+        .map(|(idx, file)| (file.path.as_path(), idx))
+        .collect();
+
+    let mut pairs = HashSet::new();
+    for (a, files) in all_files.iter().enumerate() {
+        let Some(&first) = files.first() else {
+            continue;
+        };
+
+        let dir = &tree.dirs[a].path;
+        let first = &tree.files[first];
+        let Some(content) = first.content else {
+            continue;
+        };
+        let Ok(relative) = first.path.strip_prefix(dir) else {
+            continue;
+        };
+
+        // Any directory holding this directory's first file at the same relative path might
+        // hold the rest.
+        let depth = relative.components().count();
+        for &other in &by_content[&(name(&first.path), content)] {
+            let Some(b) = tree.files[other]
+                .path
+                .ancestors()
+                .nth(depth)
+                .and_then(|path| tree.index.get(path))
+                .copied()
+            else {
+                continue;
+            };
+
+            let other_dir = &tree.dirs[b].path;
+            if a == b
+                || dir.starts_with(other_dir)
+                || other_dir.starts_with(dir)
+                || all_files[b].len() <= files.len()
+            {
+                continue;
+            }
+
+            let contained = files.iter().all(|&file| {
+                let file = &tree.files[file];
+                file.path
+                    .strip_prefix(dir)
+                    .ok()
+                    .and_then(|relative| file_index.get(other_dir.join(relative).as_path()))
+                    .is_some_and(|&found| tree.files[found].content == file.content)
+            });
+
+            if contained {
+                pairs.insert((a, b));
+            }
+        }
+    }
+
+    // Where a directory is a subset of another, so are its subdirectories of theirs.
+    let mut subsets: Vec<_> = pairs
+        .iter()
+        .filter(|&&(a, b)| {
+            let (a, b) = (&tree.dirs[a], &tree.dirs[b]);
+            let parents = a.parent.zip(b.parent);
+            !parents
+                .is_some_and(|parents| pairs.contains(&parents) && name(&a.path) == name(&b.path))
+        })
+        .map(|&(a, b)| Subset {
+            dir: tree.dirs[a].path.clone(),
+            of: tree.dirs[b].path.clone(),
+        })
+        .collect();
+
+    subsets.sort_by(|a, b| (&a.dir, &a.of).cmp(&(&b.dir, &b.of)));
+    subsets
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{plan::Action, scan::Scanner, testing::TempDir};
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn copied_trees_are_reported_once() {
+        let root = TempDir::new("copied-trees");
+        for base in ["Photos", "Backup/Photos"] {
+            write(&root, &format!("{base}/2019/a.jpg"), "a");
+            write(&root, &format!("{base}/2019/b.jpg"), "b");
+            write(&root, &format!("{base}/2020/c.jpg"), "c");
+        }
+        write(&root, "Other/a.jpg", "a");
+
+        let scan = Scanner::new(&*root).scan_directories().unwrap();
+
+        assert_eq!(scan.groups.len(), 1);
+        let group = &scan.groups[0];
+        assert_eq!(group.files, 3);
+        let mut dirs = group.dirs.clone();
+        dirs.sort();
+        assert_eq!(dirs, [root.join("Backup/Photos"), root.join("Photos")]);
+    }
+
+    #[test]
+    fn copies_overlapping_another_group_are_reported() {
+        let root = TempDir::new("overlapping-trees");
+        for base in ["R", "S", "T", "U"] {
+            write(&root, &format!("{base}/q/a.jpg"), "a");
+        }
+        for base in ["R", "S"] {
+            write(&root, &format!("{base}/b.jpg"), "b");
+        }
+        for base in ["T", "U"] {
+            write(&root, &format!("{base}/c.jpg"), "c");
+        }
+
+        let scan = Scanner::new(&*root).scan_directories().unwrap();
+        let plan = scan.plan();
+
+        let mut groups: Vec<_> = scan.groups.iter().map(|group| group.dirs.len()).collect();
+        groups.sort();
+        assert_eq!(groups, [2, 2, 4]);
+
+        let mut removed: Vec<_> = plan
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Remove { path, .. } => Some(path.clone()),
+                Action::RemoveDirectory { .. } => None,
+            })
+            .collect();
+        let count = removed.len();
+        removed.sort();
+        removed.dedup();
+        assert_eq!(removed.len(), count);
+    }
+
+    #[test]
+    fn partial_copies_are_subsets() {
+        let root = TempDir::new("subsets");
+        write(&root, "Photos/2019/a.jpg", "a");
+        write(&root, "Photos/2019/b.jpg", "b");
+        write(&root, "Photos/2019/nested/c.jpg", "c");
+        write(&root, "Backup/2019/a.jpg", "a");
+        write(&root, "Backup/2019/nested/c.jpg", "c");
+
+        let scan = Scanner::new(&*root)
+            .subsets(true)
+            .scan_directories()
+            .unwrap();
+
+        let subsets: Vec<_> = scan
+            .subsets
+            .iter()
+            .map(|subset| (subset.dir.clone(), subset.of.clone()))
+            .collect();
+        assert_eq!(subsets, [(root.join("Backup"), root.join("Photos"))]);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    borrow::Cow,
+    io,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::HashSet;
+use walkdir::WalkDir;
+
+use crate::scan::Scanner;
+
+/// Find every directory beneath the roots which holds nothing at all, or nothing but other empty
+/// directories. Roots themselves are never included. Directories are listed deepest first, so
+/// that each may be removed before its parent.
+///
+/// Anything besides a directory counts as content, including hidden files, symlinks and anything
+/// which could not be read. Ignored directories count as content of their parents and are not
+/// examined.
+pub fn find(scanner: &Scanner) -> io::Result<Vec<PathBuf>> {
+    let ignore: Vec<_> = scanner
+        .ignore
+        .iter()
+        .map(|path| {
+            path.canonicalize()
+                .map(Cow::from)
+                .unwrap_or_else(|_| Cow::from(path))
+        })
+        .collect();
+
+    let mut dirs = Vec::new();
+    let mut occupied = HashSet::new();
+    let mut seen = HashSet::new();
+
+    for root in scanner.tiers().iter().flatten() {
+        let mut entries = WalkDir::new(root).into_iter();
+        while let Some(entry) = entries.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    if let Some(path) = e.path() {
+                        occupy(&mut occupied, path, root);
+                    }
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            let is_ignored = || {
+                let canonical = path.canonicalize();
+                let canonical = canonical.as_deref().unwrap_or(path);
+                ignore.iter().any(|ignored| ignored.as_ref() == canonical)
+            };
+
+            if !entry.file_type().is_dir() || (entry.depth() > 0 && is_ignored()) {
+                occupy(&mut occupied, path, root);
+                if entry.file_type().is_dir() {
+                    entries.skip_current_dir();
+                }
+                continue;
+            }
+
+            // Roots may overlap.
+            if entry.depth() > 0 && crate::canonical(path).is_some_and(|path| seen.insert(path)) {
+                dirs.push((entry.depth(), path.to_owned()));
+            }
+        }
+    }
+
+    dirs.retain(|(_, path)| !occupied.contains(path));
+    dirs.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    Ok(dirs.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Mark each directory above a path, up to the root, as having something in it.
+fn occupy(occupied: &mut HashSet<PathBuf>, path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if !occupied.insert(dir.to_owned()) || dir == root {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{scan::Scanner, testing::TempDir};
+
+    #[test]
+    fn only_directories_without_content_are_empty() {
+        let root = TempDir::new("empty");
+        for dir in ["a/b/c", "a/d", "hidden", "full/e", "skipped"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("hidden/.marker"), "").unwrap();
+        fs::write(root.join("full/file.txt"), "content").unwrap();
+
+        let empty = Scanner::new(&*root)
+            .ignore(root.join("skipped"))
+            .empty_dirs()
+            .unwrap();
+
+        assert_eq!(
+            empty,
+            [
+                root.join("a/b/c"),
+                root.join("a/b"),
+                root.join("a/d"),
+                root.join("full/e"),
+                root.join("a"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use fmtsize::{Conventional, FmtSize};
+use image::{ImageFormat, imageops::FilterType};
+use yogi::{Scan, SharedDirs, Summary, is_image};
+
+use crate::{
+    config::PathStyle,
+    output::format_path,
+    report::{self, format_dir},
+};
+
+/// The largest dimension of an embedded thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 96;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+table { border-collapse: collapse; margin-bottom: 2em; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.4em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; cursor: pointer; user-select: none; white-space: nowrap; }
+th::after { content: ' \\2195'; color: #aaa; }
+td.num { text-align: right; white-space: nowrap; }
+td.keep { color: #1a7f37; }
+td.remove { color: #b42318; }
+ul { list-style: none; margin: 0; padding: 0; }
+img { max-width: 96px; max-height: 96px; }
+";
+
+/// Sorts a table by the clicked column, using each cell's data-sort value where it has one.
+const SCRIPT: &str = "
+document.querySelectorAll('th').forEach(th => th.addEventListener('click', () => {
+  const column = th.cellIndex;
+  const body = th.closest('table').tBodies[0];
+  const ascending = th.dataset.order !== 'asc';
+  th.dataset.order = ascending ? 'asc' : 'desc';
+  const key = row => {
+    const cell = row.cells[column];
+    return cell.dataset.sort !== undefined ? Number(cell.dataset.sort) : cell.textContent.trim();
+  };
+  const rows = Array.from(body.rows).sort((a, b) => {
+    const [x, y] = [key(a), key(b)];
+    const order = typeof x === 'number' ? x - y : x.localeCompare(y);
+    return ascending ? order : -order;
+  });
+  rows.forEach(row => body.appendChild(row));
+}));
+";
+
+/// Write a self-contained HTML report of the scan: every group with its keepers and duplicates,
+/// a thumbnail for each group of images, and summaries by directory.
+pub fn write_html(mut handle: impl Write, scan: &Scan, style: PathStyle) -> io::Result<()> {
+    let summary = Summary::new(scan, usize::MAX);
+    let paths = |paths: &[PathBuf]| -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| format_path(scan.root_of(path), path, style).into_owned())
+            .collect()
+    };
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Duplicate files</title>\n");
+    let _ = writeln!(html, "<style>{STYLE}</style>\n</head>\n<body>");
+    html.push_str("<h1>Duplicate files</h1>\n");
+    let _ = writeln!(
+        html,
+        "<p>{} groups, {} duplicates, {} to reclaim.</p>",
+        summary.groups,
+        summary.duplicates,
+        summary.reclaimable.fmt_size(Conventional)
+    );
+
+    html.push_str("<h2>Groups</h2>\n");
+    table_head(
+        &mut html,
+        &["Preview", "Files", "Size", "Reclaimable", "Keep", "Remove"],
+    );
+    for (idx, group) in scan.groups.iter().enumerate() {
+        let len = group
+            .files
+            .first()
+            .and_then(|path| scan.cache.get(path))
+            .map(|meta| meta.len)
+            .unwrap_or_default();
+
+        html.push_str("<tr>");
+        preview_cell(&mut html, group.keepers().first());
+        number_cell(&mut html, group.files.len() as u64, group.files.len());
+        number_cell(&mut html, len, len.fmt_size(Conventional));
+        let reclaimable = report::reclaimable(scan, idx);
+        number_cell(&mut html, reclaimable, reclaimable.fmt_size(Conventional));
+        list_cell(&mut html, "keep", &paths(group.keepers()));
+        list_cell(&mut html, "remove", &paths(group.duplicates()));
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody></table>\n");
+
+    if !scan.similar.is_empty() {
+        html.push_str("<h2>Similar files</h2>\n");
+        table_head(
+            &mut html,
+            &["Preview", "Files", "Distance", "Keep", "Similar"],
+        );
+        for group in &scan.similar {
+            html.push_str("<tr>");
+            preview_cell(&mut html, group.keepers().first());
+            number_cell(&mut html, group.files.len() as u64, group.files.len());
+            number_cell(&mut html, group.distance.into(), group.distance);
+            list_cell(&mut html, "keep", &paths(group.keepers()));
+            list_cell(&mut html, "remove", &paths(group.duplicates()));
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody></table>\n");
+    }
+
+    if !scan.archived.is_empty() {
+        html.push_str("<h2>Files duplicated in archives</h2>\n");
+        table_head(&mut html, &["Size", "Archived", "Keep", "Remove"]);
+        for group in &scan.archived {
+            html.push_str("<tr>");
+            number_cell(&mut html, group.len, group.len.fmt_size(Conventional));
+            list_cell(&mut html, "keep", &paths(&group.members));
+            list_cell(&mut html, "keep", &paths(group.keepers()));
+            list_cell(&mut html, "remove", &paths(group.duplicates()));
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody></table>\n");
+    }
+
+    html.push_str("<h2>Directories</h2>\n");
+    table_head(&mut html, &["Directory", "Duplicates", "Reclaimable"]);
+    for (dir, count, bytes) in &summary.directories {
+        html.push_str("<tr>");
+        text_cell(&mut html, &format_dir(scan, dir, style));
+        number_cell(&mut html, *count as u64, count);
+        number_cell(&mut html, *bytes, bytes.fmt_size(Conventional));
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody></table>\n");
+
+    html.push_str("<h2>Directories sharing files</h2>\n");
+    table_head(
+        &mut html,
+        &["Directory", "Directory", "Shared files", "Shared size"],
+    );
+    for pair in SharedDirs::find(scan) {
+        html.push_str("<tr>");
+        for dir in &pair.dirs {
+            text_cell(&mut html, &format_dir(scan, dir, style));
+        }
+        number_cell(&mut html, pair.files as u64, pair.files);
+        number_cell(&mut html, pair.bytes, pair.bytes.fmt_size(Conventional));
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody></table>\n");
+
+    let _ = writeln!(html, "<script>{SCRIPT}</script>\n</body>\n</html>");
+    handle.write_all(html.as_bytes())?;
+    handle.flush()
+}
+
+fn table_head(html: &mut String, columns: &[&str]) {
+    html.push_str("<table><thead><tr>");
+    for column in columns {
+        let _ = write!(html, "<th>{}</th>", escape(column));
+    }
+    html.push_str("</tr></thead><tbody>\n");
+}
+
+fn number_cell(html: &mut String, sort: u64, text: impl ToString) {
+    let _ = write!(
+        html,
+        "<td class=\"num\" data-sort=\"{sort}\">{}</td>",
+        escape(&text.to_string())
+    );
+}
+
+fn text_cell(html: &mut String, text: &str) {
+    let _ = write!(html, "<td>{}</td>", escape(text));
+}
+
+fn list_cell(html: &mut String, class: &str, items: &[String]) {
+    let _ = write!(html, "<td class=\"{class}\"><ul>");
+    for item in items {
+        let _ = write!(html, "<li>{}</li>", escape(item));
+    }
+    html.push_str("</ul></td>");
+}
+
+fn preview_cell(html: &mut String, path: Option<&PathBuf>) {
+    match path.and_then(|path| thumbnail(path)) {
+        Some(data) => {
+            let _ = write!(
+                html,
+                "<td><img alt=\"\" src=\"data:image/jpeg;base64,{data}\"></td>"
+            );
+        }
+        None => html.push_str("<td></td>"),
+    }
+}
+
+/// A small JPEG of an image, base64-encoded for embedding, or `None` if the file isn't an image
+/// which can be decoded.
+fn thumbnail(path: &Path) -> Option<String> {
+    if !is_image(path) {
+        return None;
+    }
+
+    let image = image::open(path).ok()?;
+    let thumbnail = image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle);
+
+    // JPEG has no alpha channel.
+    let mut bytes = Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(thumbnail.to_rgb8())
+        .write_to(&mut bytes, ImageFormat::Jpeg)
+        .ok()?;
+    Some(STANDARD.encode(bytes.into_inner()))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for u in text.chars() {
+        match u {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            u => escaped.push(u),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,131 @@
+//! Find duplicated files and, optionally, remove them.
+//!
+//! A [`Scanner`] describes what to examine. Scanning produces a [`Scan`], which holds a
+//! [`Group`] for each set of identical files, ordered so that the files to be kept come first.
+//! Nothing on disk is touched until a [`Plan`] is built from the scan and executed.
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use yogi::{Scanner, SortOrder};
+//!
+//! let scan = Scanner::new("./photos")
+//!     .ignore("./photos/originals")
+//!     .sort(&[SortOrder::Prefer("./photos/albums".into()), SortOrder::Oldest])
+//!     .scan()?;
+//!
+//! for group in &scan.groups {
+//!     println!("keeping {}", group.keepers()[0].display());
+//! }
+//!
+//! let removed = scan.plan().execute()?;
+//! println!("removed {} files ({} bytes)", removed.files, removed.bytes);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use walkdir::{DirEntry, WalkDir};
+
+mod archive;
+mod dirs;
+mod empty;
+mod meta;
+mod multiple;
+mod plan;
+mod progress;
+mod rank;
+mod saved;
+mod scan;
+mod similar;
+mod single;
+mod sort;
+mod stats;
+#[cfg(test)]
+mod testing;
+mod text;
+mod token;
+
+pub use archive::Archived;
+pub use dirs::{DirectoryGroup, DirectoryScan, Subset};
+pub use imprint::Imprint;
+pub use meta::{Meta, Metacache};
+pub use plan::{Action, Plan, Removed};
+pub use rank::{PathRanker, Rank};
+pub use saved::{Record, SavedGroup, SavedPlan, Stale, StaleReason};
+pub use scan::{Group, GroupOrder, ParseGroupOrderError, Scan, Scanner, Similar};
+pub use similar::{ImageHash, ParseImageHashError, is_image};
+pub use sort::{
+    Alphabetical, Avoid, Depth, MetaSorter, ParseSortOrderError, PathSorter, Prefer, Reason, Rule,
+    SortOrder, Strategy, Timestamp, get_sorter, get_sorter_with,
+};
+pub use stats::{SharedDirs, Stats, Summary};
+pub use text::TextHash;
+
+fn list_entries<'a>(
+    root: impl AsRef<Path>,
+    recurse: bool,
+    ignore: &'a [PathBuf],
+) -> Box<dyn Iterator<Item = DirEntry> + 'a> {
+    let ignore: Vec<_> = ignore
+        .iter()
+        .map(|path| {
+            path.canonicalize()
+                .map(Cow::from)
+                .unwrap_or_else(|_| Cow::from(path))
+        })
+        .collect();
+
+    fn is_hidden(path: &Path) -> bool {
+        let Some(file_name) = path.file_name() else {
+            return false;
+        };
+
+        file_name
+            .as_encoded_bytes()
+            .starts_with(OsStr::new(".").as_encoded_bytes())
+    }
+
+    fn is_file(entry: &DirEntry) -> bool {
+        entry.file_type().is_file() && entry.path().ancestors().all(|path| !is_hidden(path))
+    }
+
+    if recurse {
+        Box::new(
+            WalkDir::new(root)
+                .into_iter()
+                .filter_entry(move |entry| {
+                    entry.path().is_file()
+                        || !ignore
+                            .iter()
+                            .any(|ignored_path| ignored_path.as_ref() == entry.path())
+                })
+                .filter_map(Result::ok)
+                .filter(is_file),
+        )
+    } else {
+        Box::new(
+            WalkDir::new(root)
+                .max_depth(1)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(is_file),
+        )
+    }
+}
+
+/// The canonical path of an entry found by [`list_entries`], or `None` if it has vanished or can't
+/// be resolved since. Files come and go while we walk, and such entries are skipped rather than
+/// aborting the whole scan.
+fn canonical(path: &Path) -> Option<PathBuf> {
+    path.canonicalize().ok()
+}
+
+/// The metadata of an entry found by [`list_entries`], or `None`, as for [`canonical`].
+fn metadata(path: &Path) -> Option<Meta> {
+    path.metadata().ok().map(Meta::from)
+}
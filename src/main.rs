@@ -1,15 +1,19 @@
-use std::{borrow::Cow, ffi::OsStr, fs, io, path::Path};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 mod config;
-mod meta;
-mod multiple;
-mod rank;
-mod single;
+mod html;
+mod output;
+mod report;
+mod settings;
 
-use config::Args;
-use imprint::Imprint;
-use meta::Metacache;
-use walkdir::{DirEntry, WalkDir};
+use config::{Args, Command, ConfigCommand, Format, ReportCommand};
+use fmtsize::{Conventional, FmtSize};
+use yogi::{Plan, SavedPlan};
 
 fn main() {
     if let Err(e) = run(Args::parse()) {
@@ -19,135 +23,148 @@ fn main() {
 }
 
 fn run(args: Args) -> io::Result<()> {
-    let ignore: Vec<_> = args.ignore.iter().map(Path::new).collect();
-
-    if args.compare.is_empty() {
-        single::process(
-            args.path(),
-            args.sort_order(),
-            args.force,
-            args.recurse(),
-            &ignore,
-        )
-    } else {
-        multiple::process(
-            args.path(),
-            &args.compare,
-            args.force,
-            args.recurse(),
-            &ignore,
-        )
+    let args = args.configure()?;
+
+    if let Some(Command::Config(ConfigCommand::Show)) = &args.command {
+        let settings = toml::to_string(&args.settings())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        print!("{settings}");
+        return Ok(());
     }
-}
 
-fn list_entries<'a>(
-    root: impl AsRef<Path>,
-    recurse: bool,
-    ignore: &'a [&Path],
-) -> Box<dyn Iterator<Item = DirEntry> + 'a> {
-    let ignore: Vec<_> = ignore
-        .iter()
-        .map(|&path| {
-            path.canonicalize()
-                .map(Cow::from)
-                .unwrap_or_else(|_| Cow::from(path))
-        })
-        .collect();
-
-    fn is_hidden(path: &Path) -> bool {
-        let Some(file_name) = path.file_name() else {
-            return false;
-        };
+    if args.output.is_some() && args.format() == Format::Text {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--output requires --format json or html",
+        ));
+    }
+
+    let applying = matches!(args.command, Some(Command::Apply { .. }));
+    if args.prune_empty_dirs && !args.force && !applying {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--prune-empty-dirs requires --force",
+        ));
+    }
 
-        file_name
-            .as_encoded_bytes()
-            .starts_with(OsStr::new(".").as_encoded_bytes())
+    if let Some(Command::Apply { plan }) = &args.command {
+        let saved = SavedPlan::read(plan)?;
+        let (plan, stale) = saved.validate();
+        for stale in &stale {
+            eprintln!("refused {}: {}", stale.path.display(), stale.reason);
+        }
+
+        execute(&args, &plan, &saved.roots)?;
+        if !stale.is_empty() {
+            println!("Refused {} changed files", stale.len());
+        }
+        return Ok(());
     }
 
-    fn is_file(entry: &DirEntry) -> bool {
-        entry.file_type().is_file() && entry.path().ancestors().all(|path| !is_hidden(path))
+    let scanner = args.scanner()?;
+    if let Some(Command::Report(ReportCommand::Dirs { .. })) = &args.command {
+        let scan = scanner.scan()?;
+        return match args.format() {
+            Format::Text => output::pretty_print_shared_dirs(&scan, args.path_style()),
+            Format::Json => {
+                report::write_shared_dirs_json(report_output(&args)?, &scan, args.path_style())
+            }
+            Format::Html => html::write_html(report_output(&args)?, &scan, args.path_style()),
+        };
     }
 
-    if recurse {
-        Box::new(
-            WalkDir::new(root)
+    if args.empty_dirs {
+        let dirs = scanner.empty_dirs()?;
+        return if args.force {
+            let removed = Plan::for_empty_dirs(&dirs).execute()?;
+            println!("Removed {} empty directories", removed.dirs);
+            Ok(())
+        } else {
+            let roots: Vec<_> = args
+                .paths()
                 .into_iter()
-                .filter_entry(move |entry| {
-                    entry.path().is_file()
-                        || !ignore
-                            .iter()
-                            .any(|ignored_path| ignored_path.as_ref() == entry.path())
-                })
-                .filter_map(Result::ok)
-                .filter(is_file),
-        )
-    } else {
-        Box::new(
-            WalkDir::new(root)
-                .max_depth(1)
-                .into_iter()
-                .filter_map(Result::ok)
-                .filter(is_file),
-        )
+                .chain(args.compare.iter().map(AsRef::as_ref))
+                .map(Path::new)
+                .collect();
+            output::pretty_print_empty_dirs(&dirs, &roots, args.path_style())
+        };
     }
-}
 
-fn deconflict<'a>(
-    groups: impl IntoIterator<Item = (Imprint, Vec<&'a Path>)>,
-    cache: &Metacache,
-) -> io::Result<(usize, u64)> {
-    let mut count = 0;
-    let mut size = 0;
-
-    let conflicts = groups.into_iter().flat_map(|x| x.1.into_iter().skip(1));
-    for path in conflicts {
-        count += 1;
-        size += cache.get(path).map(|x| x.len).unwrap_or_default();
-        fs::remove_file(path)?;
+    if args.directories {
+        let scan = scanner.scan_directories()?;
+        return if args.force {
+            execute(&args, &scan.plan(), &scan.tiers.concat())?;
+            Ok(())
+        } else {
+            output::pretty_print_directories(&scan, args.path_style())
+        };
     }
 
-    Ok((count, size))
-}
+    let scan = scanner.scan()?;
+    if let Some(Command::Scan {
+        save: Some(path), ..
+    }) = &args.command
+    {
+        SavedPlan::new(&scan).write(path)?;
+        let count: usize = scan.groups.iter().map(|x| x.duplicates().len()).sum();
+        println!("Saved {count} duplicates to {}", path.display());
+        return Ok(());
+    }
 
-fn pretty_print_conflicts<'a>(
-    groups: impl IntoIterator<Item = (Imprint, Vec<&'a Path>)>,
-    metacache: &Metacache,
-) -> io::Result<()> {
-    use fmtsize::{Conventional, FmtSize};
-    use std::io::Write;
-
-    let mut handle = io::stdout().lock();
-    let mut count = 0;
-    let mut size = 0;
-
-    for (imprint, group) in groups {
-        // One of these files is NOT a duplicate; it's the primary.
-        let n = group.len() - 1;
-
-        count += n;
-        size += n as u64
-            * group
-                .first()
-                .and_then(|&path| metacache.get(path).map(|x| x.len))
-                .unwrap_or_default();
-
-        writeln!(
-            handle,
-            "{}\n================================================================",
-            imprint,
-        )?;
-
-        for path in group {
-            writeln!(handle, "{}", path.display())?;
+    let explain = args.explain.then_some(&scanner);
+
+    let action_time = if args.force {
+        let mut plan = scan.plan();
+        if args.remove_similar {
+            plan = plan.include_similar(&scan);
         }
-        writeln!(handle)?;
-    }
 
-    writeln!(
-        handle,
-        "{} duplicates ({})",
-        count,
-        size.fmt_size(Conventional)
-    )?;
+        Some(execute(&args, &plan, &scan.tiers.concat())?)
+    } else if args.format() == Format::Json {
+        let handle = report_output(&args)?;
+        return report::write_json(handle, &scan, args.path_style(), args.stats);
+    } else if args.format() == Format::Html {
+        return html::write_html(report_output(&args)?, &scan, args.path_style());
+    } else if args.compare.is_empty() {
+        output::pretty_print_conflicts(&scan, args.path_style(), explain)?;
+        output::pretty_print_similar(&scan, args.path_style())?;
+        output::pretty_print_archived(&scan, args.path_style())?;
+        None
+    } else {
+        output::pretty_print_comparison(&scan, args.path_style(), explain)?;
+        output::pretty_print_similar(&scan, args.path_style())?;
+        output::pretty_print_archived(&scan, args.path_style())?;
+        None
+    };
+
+    if args.stats {
+        output::pretty_print_stats(&scan, args.path_style(), action_time)?;
+    }
     Ok(())
 }
+
+/// Where to write a JSON or HTML report: the file given by --output, or else stdout. Buffered
+/// output is lost unless flushed, so each report flushes the handle once written.
+fn report_output(args: &Args) -> io::Result<Box<dyn Write>> {
+    Ok(match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    })
+}
+
+/// Carry out a plan, returning the time it took.
+fn execute(args: &Args, plan: &Plan, roots: &[PathBuf]) -> io::Result<Duration> {
+    let started = Instant::now();
+    let removed = plan.execute()?;
+    println!(
+        "Removed {} files ({})",
+        removed.files,
+        removed.bytes.fmt_size(Conventional)
+    );
+
+    if args.prune_empty_dirs {
+        let count = plan.prune_empty_dirs(roots)?;
+        println!("Removed {count} empty directories");
+    }
+    Ok(started.elapsed())
+}
@@ -1,14 +1,71 @@
-use std::{fs, path::Path, time::SystemTime};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-pub type Metacache<'a> = hashbrown::HashMap<&'a Path, Meta>;
+use hashbrown::HashMap;
 
+/// File metadata for the files examined by a scan, keyed by path.
+#[derive(Clone, Debug, Default)]
+pub struct Metacache(HashMap<PathBuf, Meta>);
+
+impl Metacache {
+    /// An empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metadata recorded for a file, if any.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&Meta> {
+        self.0.get(path.as_ref())
+    }
+
+    /// Record a file's metadata, returning whatever was recorded for it before.
+    pub fn insert(&mut self, path: PathBuf, meta: Meta) -> Option<Meta> {
+        self.0.insert(path, meta)
+    }
+
+    /// The number of files recorded.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no file is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Each file recorded, with its metadata, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Meta)> {
+        self.0.iter().map(|(path, meta)| (path.as_path(), meta))
+    }
+}
+
+impl FromIterator<(PathBuf, Meta)> for Metacache {
+    fn from_iter<I: IntoIterator<Item = (PathBuf, Meta)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// The parts of a file's metadata used to compare and report on it.
 #[derive(Clone, Debug)]
 pub struct Meta {
+    /// When the file was created, if the file system records it.
+    //
     // The only time I've ever seen this fail was in pulling metadata for files on a Windows
     // volume from a Linux host. Whether it can happen under any other circumstances, God knows.
     // Hopefully God also knows what happens if you prioritize files by created date and they
-    // don't freaking have one.
+    // don't freaking have one. (As it turns out, they sort after the files that do.)
     pub created: Option<SystemTime>,
+
+    /// When the file was last modified, if the file system records it.
+    pub modified: Option<SystemTime>,
+
+    /// When the file was last accessed, if the file system records it.
+    pub accessed: Option<SystemTime>,
+
+    /// The file's length, in bytes.
     pub len: u64,
 }
 
@@ -16,6 +73,8 @@ impl From<fs::Metadata> for Meta {
     fn from(meta: fs::Metadata) -> Self {
         Self {
             created: meta.created().ok(),
+            modified: meta.modified().ok(),
+            accessed: meta.accessed().ok(),
             len: meta.len(),
         }
     }
@@ -1,200 +1,208 @@
 use std::{
-    fs,
-    io::{self, Write},
-    path::Path,
+    io,
+    path::{Path, PathBuf},
 };
 
 use bumpalo::Bump;
-use fmtsize::{Conventional, FmtSize};
-use hashbrown::{HashMap, HashSet, hash_map::EntryRef};
+use hashbrown::{HashMap, HashSet};
 use imprint::Imprint;
 
-use crate::meta::{Meta, Metacache};
+use crate::{
+    meta::{Meta, Metacache},
+    progress::Progress,
+    scan::{Group, Scan, Scanner},
+    sort::PathSorter,
+};
 
-#[derive(Clone, Debug, Default)]
+/// Identical files, sorted into the tiers in which they were found.
+#[derive(Clone, Debug)]
 struct Conflict<'a> {
-    base_files: Vec<&'a Path>,
-    compare_files: Vec<&'a Path>,
+    tiers: Vec<Vec<&'a Path>>,
 }
 
-pub fn process(
-    path: &str,
-    compare: &[impl AsRef<Path>],
-    force: bool,
-    recurse: bool,
-    ignore: &[&Path],
-) -> io::Result<()> {
+pub fn scan(
+    scanner: &Scanner,
+    sorter: &dyn PathSorter,
+    progress: &mut Progress,
+) -> io::Result<Scan> {
     let paths = Bump::new();
     let mut context = Context {
-        root: path,
-        compare_to: compare,
+        scanner,
+        tiers: scanner.tiers(),
         paths: &paths,
-        cache: Metacache::new(),
-        ignore,
+        cache: HashMap::new(),
     };
 
-    let conflicts = get_conflicts(&mut context, recurse)?;
+    let conflicts = get_conflicts(&mut context, progress)?;
 
-    if force {
-        let mut count = 0usize;
-        let mut size = 0u64;
-        for path in conflicts
+    let mut cache = Metacache::new();
+    let mut to_owned = |paths: Vec<&Path>| -> Vec<PathBuf> {
+        paths
             .into_iter()
-            .flat_map(|entry| entry.1.compare_files)
-        {
-            fs::remove_file(path)?;
-            count += 1;
-            size += context
-                .cache
-                .get(path)
-                .map(|meta| meta.len)
-                .unwrap_or_default();
-        }
-        println!("Removed {} files ({})", count, size.fmt_size(Conventional));
-    } else {
-        pretty_print_conflicts(conflicts, &context.cache)?;
-    }
-
-    Ok(())
-}
+            .map(|path| {
+                if let Some(meta) = context.cache.get(path) {
+                    cache.insert(path.to_owned(), meta.clone());
+                }
+                path.to_owned()
+            })
+            .collect()
+    };
 
-#[derive(Debug)]
-struct Context<'a, T> {
-    root: &'a str,
-    compare_to: &'a [T],
-    paths: &'a Bump,
-    cache: Metacache<'a>,
-    ignore: &'a [&'a Path],
-}
+    let conflicts: Vec<(Imprint, Vec<Vec<PathBuf>>)> = conflicts
+        .map(|(imprint, conflict)| {
+            (
+                imprint,
+                conflict.tiers.into_iter().map(&mut to_owned).collect(),
+            )
+        })
+        .collect();
 
-fn get_conflicts<'a, T: AsRef<Path>>(
-    context: &mut Context<'a, T>,
-    recurse: bool,
-) -> io::Result<impl Iterator<Item = (Imprint, Conflict<'a>)> + use<'a, T>> {
-    let base_files: HashSet<&Path> = super::list_entries(context.root, recurse, context.ignore)
-        .filter_map(|entry| entry.path().canonicalize().ok())
-        .map(|entry| &**context.paths.alloc(entry))
+    // Files in a higher tier always come before those in a lower tier, while files within a tier
+    // are ranked by the sorter. When comparing against the roots, every base file is kept and
+    // ranking them only decides which is the primary; if there are no base files, this is a
+    // duplicate within the compare paths, and only the first compare file is kept.
+    let keep_base = !scanner.compare.is_empty();
+    let groups = conflicts
+        .into_iter()
+        .map(|(imprint, tiers)| {
+            let keep = if keep_base { tiers[0].len().max(1) } else { 1 };
+            let files = tiers
+                .into_iter()
+                .flat_map(|mut files| {
+                    sorter.sort(&mut files, &cache);
+                    files
+                })
+                .collect();
+
+            Group {
+                imprint,
+                files,
+                keep,
+            }
+        })
         .collect();
 
-    // We're going to attempt to prevent files in the basic set from appearing in this set.
-    let compare_files: HashSet<_> = context
-        .compare_to
+    let tiers = context
+        .tiers
         .iter()
-        .flat_map(|path| super::list_entries(path, recurse, context.ignore))
-        .filter_map(|entry| entry.path().canonicalize().ok())
-        .map(|entry| &**context.paths.alloc(entry))
+        .map(|roots| {
+            roots
+                .iter()
+                .map(|root| root.canonicalize().unwrap_or_else(|_| root.clone()))
+                .collect()
+        })
         .collect();
 
-    let compare_files: Vec<_> = compare_files.difference(&base_files).copied().collect();
-    let base_files_by_length: HashMap<_, _> = by_length(base_files.into_iter())?;
-    let mut files_by_imprint: HashMap<Imprint, Conflict> = HashMap::new();
-
-    // Here be dragons.
-
-    // Basically, the first thing we do is populate files_by_imprint with any *potential* conflicts
-    // (as determined by file length) from the base file list. The key for this process is the file
-    // imprint, but the value is a struct called Conflict and all base file paths are inserted here
-    // onto the conflict.base_files member. This process is performed for each path in the
-    // comparison set.
+    Ok(Scan {
+        groups,
+        cache,
+        tiers,
+        similar: Vec::new(),
+        archived: Vec::new(),
+        stats: Default::default(),
+    })
+}
 
-    // We need this duplicate filter because we will get re-imprint base files for each file of
-    // matching length in the comparison set, and this is... undesirable.
-    let mut duplicate_filter = HashSet::new();
+struct Context<'a> {
+    scanner: &'a Scanner,
+    tiers: Vec<Vec<PathBuf>>,
+    paths: &'a Bump,
+    cache: HashMap<&'a Path, Meta>,
+}
 
-    for path in compare_files {
-        let meta: Meta = path.metadata()?.into();
-        if let Some(potential_conflicts) = base_files_by_length.get(&meta.len) {
-            let imprints = potential_conflicts
-                .iter()
-                .copied()
-                .filter(|&path| duplicate_filter.insert(path))
-                .filter_map(|path| Imprint::new(path).ok().map(|imprint| (path, imprint)));
+fn get_conflicts<'a>(
+    context: &mut Context<'a>,
+    progress: &mut Progress,
+) -> io::Result<impl Iterator<Item = (Imprint, Conflict<'a>)> + use<'a>> {
+    let scanner = context.scanner;
+    let tier_count = context.tiers.len();
+    let keep_base = !scanner.compare.is_empty();
+    let dedupe = scanner.dedupe_compare;
+
+    // A file reachable from more than one tier belongs to the first of them. Otherwise, comparing
+    // a tree with its own subtree would find every file in the subtree to be its own duplicate.
+    let mut seen = HashSet::new();
+    let mut files_by_length: HashMap<u64, Vec<(usize, &Path)>> = HashMap::new();
+
+    for (tier, roots) in context.tiers.iter().enumerate() {
+        let entries = roots
+            .iter()
+            .flat_map(|path| super::list_entries(path, scanner.recurse, &scanner.ignore));
+
+        for entry in entries {
+            progress.walked();
+            let Some(path) = crate::canonical(entry.path()) else {
+                continue;
+            };
+
+            let path = &**context.paths.alloc(path);
+            if !seen.insert(path) {
+                continue;
+            }
 
-            for (base_path, imprint) in imprints {
-                files_by_imprint
-                    .entry(imprint)
+            let Some(meta) = crate::metadata(path) else {
+                continue;
+            };
+            if scanner.accepts(path, &meta) {
+                progress.examined(meta.len);
+                files_by_length
+                    .entry(meta.len)
                     .or_default()
-                    .base_files
-                    .push(base_path);
+                    .push((tier, path));
+                context.cache.insert(path, meta);
             }
         }
-
-        // Now we're on to step two, which is to populate only occupied entries with the paths of
-        // files with matching imprints from the comparison set. In theory, only the
-        // conflict.compare_files member is modified here, and only files from the set of
-        // compared paths rather than files from the base path. However, when pretty-printed,
-        // these results pretty much ALWAYS look WEIRD. Specifically, they look as though the base
-        // file and compare file have the same filename. (As of May 26, 2022.)
-
-        let imprint = Imprint::new(path)?;
-        if let EntryRef::Occupied(mut conflicts) = files_by_imprint.entry_ref(&imprint) {
-            conflicts.get_mut().compare_files.push(path);
-            context.cache.insert(path, meta);
-        }
     }
 
-    Ok(files_by_imprint
-        .into_iter()
-        .filter(|entry| !entry.1.compare_files.is_empty()))
-}
-
-fn pretty_print_conflicts<'a>(
-    groups: impl IntoIterator<Item = (Imprint, Conflict<'a>)>,
-    cache: &Metacache,
-) -> io::Result<()> {
-    let mut handle = io::stdout().lock();
-    let mut count = 0;
-    let mut size = 0;
-
-    for (imprint, conflict) in groups {
-        count += conflict.compare_files.len();
-        size += conflict.compare_files.len() as u64
-            * conflict
-                .compare_files
-                .first()
-                .copied()
-                .and_then(|path| cache.get(path).map(|cx| cx.len))
-                .unwrap_or_default();
-
-        writeln!(
-            handle,
-            "{imprint}\n-------------------------- base files --------------------------",
-        )?;
-
-        for &path in &conflict.base_files {
-            writeln!(handle, "{}", path.file_name().unwrap().to_string_lossy())?;
-        }
+    // Only files sharing a length with a file they could conflict with are worth hashing.
+    let candidates: Vec<_> = files_by_length
+        .into_values()
+        .filter(|files| {
+            let base = files.iter().filter(|(tier, _)| *tier == 0).count();
+            is_conflict(keep_base, dedupe, base, files.len() - base)
+        })
+        .flatten()
+        .collect();
 
-        writeln!(
-            handle,
-            "-------------------------- duplicates --------------------------",
-        )?;
+    let bytes = candidates
+        .iter()
+        .filter_map(|(_, path)| context.cache.get(path))
+        .map(|meta| meta.len)
+        .sum();
+    progress.candidates(candidates.len(), bytes);
 
-        for &path in &conflict.compare_files {
-            writeln!(handle, "{}", path.file_name().unwrap().to_string_lossy())?;
-        }
-        writeln!(handle)?;
+    let mut files_by_imprint: HashMap<Imprint, Conflict> = HashMap::new();
+    for (tier, path) in candidates {
+        let imprint = Imprint::new(path)?;
+        progress.hashed(context.cache.get(path).map(|x| x.len).unwrap_or_default());
+        files_by_imprint
+            .entry(imprint)
+            .or_insert_with(|| Conflict {
+                tiers: vec![Vec::new(); tier_count],
+            })
+            .tiers[tier]
+            .push(path);
     }
 
-    writeln!(
-        handle,
-        "{count} duplicates ({})",
-        size.fmt_size(Conventional)
-    )?;
-
-    Ok(())
+    Ok(files_by_imprint.into_iter().filter(move |(_, conflict)| {
+        let base = conflict.tiers[0].len();
+        let rest = conflict.tiers[1..].iter().map(Vec::len).sum();
+        is_conflict(keep_base, dedupe, base, rest)
+    }))
 }
 
-fn by_length<'a, I>(paths: I) -> io::Result<HashMap<u64, Vec<&'a Path>>>
-where
-    I: IntoIterator<Item = &'a Path> + 'a,
-{
-    let mut map = HashMap::new();
-    for path in paths {
-        let meta = fs::metadata(path)?;
-        map.entry(meta.len()).or_insert_with(Vec::new).push(path);
+/// Decide whether a set of files includes anything to be removed, given the number of files in
+/// the first tier and the number in all other tiers.
+///
+/// When comparing against the roots, base files are never removed, and compare files are only
+/// removed if they match a base file (or if we're deduplicating the compare set). Otherwise, any
+/// two files conflict, whatever their tiers.
+pub(crate) fn is_conflict(keep_base: bool, dedupe: bool, base: usize, rest: usize) -> bool {
+    if keep_base {
+        rest > 0 && (base > 0 || dedupe && rest > 1)
+    } else {
+        base + rest > 1
     }
-    Ok(map)
 }
 
 #[cfg(test)]
@@ -202,25 +210,27 @@ mod tests {
     use std::path::Path;
 
     use bumpalo::Bump;
+    use hashbrown::HashMap;
 
-    use crate::meta::Metacache;
+    use crate::{progress::Progress, scan::Scanner, sort::Depth};
 
     use super::{Context, get_conflicts};
 
     #[test]
     fn subtree_comparisons_ignore_subtree_files() {
         let paths = Bump::new();
+        let scanner =
+            Scanner::new("./resource/test-folder/subfolder").compare("./resource/test-folder");
         let mut context = Context {
-            root: "./resource/test-folder/subfolder",
-            compare_to: &["./resource/test-folder"],
+            scanner: &scanner,
+            tiers: scanner.tiers(),
             paths: &paths,
-            cache: Metacache::new(),
-            ignore: &[],
+            cache: HashMap::new(),
         };
 
-        let actual: Vec<_> = get_conflicts(&mut context, true)
+        let actual: Vec<_> = get_conflicts(&mut context, &mut Progress::new(false))
             .unwrap()
-            .flat_map(|(_, conflict)| conflict.compare_files)
+            .flat_map(|(_, mut conflict)| conflict.tiers.remove(1))
             .collect();
         let expected = &[Path::new("./resource/test-folder/a.txt")
             .canonicalize()
@@ -232,27 +242,50 @@ mod tests {
     #[test]
     fn subtree_comparisons_do_not_result_in_duplicate_base_files() {
         let paths = Bump::new();
+        let scanner =
+            Scanner::new("./resource/test-folder/subfolder").compare("./resource/test-folder");
         let mut context = Context {
-            root: "./resource/test-folder/subfolder",
-            compare_to: &["./resource/test-folder"],
+            scanner: &scanner,
+            tiers: scanner.tiers(),
             paths: &paths,
-            cache: Metacache::new(),
-            ignore: &[],
+            cache: HashMap::new(),
         };
 
-        let actual: Vec<_> = get_conflicts(&mut context, true)
+        // Files within a tier arrive in the order they were walked, which is up to the file system.
+        let mut actual: Vec<_> = get_conflicts(&mut context, &mut Progress::new(false))
             .unwrap()
-            .flat_map(|(_, conflict)| conflict.base_files)
+            .flat_map(|(_, mut conflict)| conflict.tiers.remove(0))
             .collect();
+        actual.sort();
         let expected = &[
-            Path::new("./resource/test-folder/subfolder/sub-a.txt")
+            Path::new("./resource/test-folder/subfolder/sub-a-copy.txt")
                 .canonicalize()
                 .unwrap(),
-            Path::new("./resource/test-folder/subfolder/sub-a-copy.txt")
+            Path::new("./resource/test-folder/subfolder/sub-a.txt")
                 .canonicalize()
                 .unwrap(),
         ];
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn higher_priority_roots_win_regardless_of_sort_order() {
+        let scan = Scanner::new("./resource/test-folder/a.txt")
+            .root("./resource/test-folder/subfolder")
+            .priority_order(true)
+            .sorter(Box::new(Depth::deepest()))
+            .scan()
+            .unwrap();
+
+        let [group] = &scan.groups[..] else {
+            panic!("expected exactly one group");
+        };
+        let keeper = Path::new("./resource/test-folder/a.txt")
+            .canonicalize()
+            .unwrap();
+
+        assert_eq!(group.keepers(), &[keeper]);
+        assert_eq!(group.duplicates().len(), 2);
+    }
 }
@@ -0,0 +1,460 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use fmtsize::{Conventional, FmtSize};
+use yogi::{DirectoryScan, Group, Scan, Scanner, SharedDirs, Summary};
+
+use crate::{
+    config::PathStyle,
+    report::{self, TOP},
+};
+
+pub fn pretty_print_conflicts(
+    scan: &Scan,
+    style: PathStyle,
+    explain: Option<&Scanner>,
+) -> io::Result<()> {
+    let mut handle = io::stdout().lock();
+    let mut count = 0;
+    let mut size = 0;
+
+    for group in &scan.groups {
+        // One of these files is NOT a duplicate; it's the primary.
+        let n = group.files.len() - 1;
+
+        count += n;
+        size += n as u64
+            * group
+                .files
+                .first()
+                .and_then(|path| scan.cache.get(path).map(|x| x.len))
+                .unwrap_or_default();
+
+        writeln!(
+            handle,
+            "{}\n================================================================",
+            group.imprint,
+        )?;
+
+        for path in &group.files {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+
+        if let Some(scanner) = explain {
+            write_explanation(&mut handle, scan, group, scanner, false)?;
+        }
+        writeln!(handle)?;
+    }
+
+    writeln!(
+        handle,
+        "{} duplicates ({})",
+        count,
+        size.fmt_size(Conventional)
+    )?;
+    Ok(())
+}
+
+pub fn pretty_print_comparison(
+    scan: &Scan,
+    style: PathStyle,
+    explain: Option<&Scanner>,
+) -> io::Result<()> {
+    let mut handle = io::stdout().lock();
+    let mut count = 0;
+    let mut size = 0;
+
+    for group in &scan.groups {
+        let duplicates = group.duplicates();
+        count += duplicates.len();
+        size += duplicates.len() as u64
+            * duplicates
+                .first()
+                .and_then(|path| scan.cache.get(path).map(|cx| cx.len))
+                .unwrap_or_default();
+
+        // Without a base file, this is a duplicate within the compare paths.
+        let header = if group
+            .keepers()
+            .iter()
+            .any(|path| scan.tier_of(path) == Some(0))
+        {
+            "-------------------------- base files --------------------------"
+        } else {
+            "----------------------------- kept -----------------------------"
+        };
+        writeln!(handle, "{}\n{header}", group.imprint)?;
+
+        for path in group.keepers() {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+
+        writeln!(
+            handle,
+            "-------------------------- duplicates --------------------------",
+        )?;
+
+        for path in duplicates {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+
+        if let Some(scanner) = explain {
+            write_explanation(&mut handle, scan, group, scanner, true)?;
+        }
+        writeln!(handle)?;
+    }
+
+    writeln!(
+        handle,
+        "{count} duplicates ({})",
+        size.fmt_size(Conventional)
+    )?;
+
+    Ok(())
+}
+
+pub fn pretty_print_similar(scan: &Scan, style: PathStyle) -> io::Result<()> {
+    if scan.similar.is_empty() {
+        return Ok(());
+    }
+
+    let mut handle = io::stdout().lock();
+    let mut count = 0;
+    let mut size = 0;
+
+    writeln!(handle)?;
+    for group in &scan.similar {
+        let duplicates = group.duplicates();
+        count += duplicates.len();
+        size += duplicates
+            .iter()
+            .filter_map(|path| scan.cache.get(path).map(|x| x.len))
+            .sum::<u64>();
+
+        let header = match group.distance {
+            0 => String::from("similar"),
+            1 => String::from("similar (within 1 bit)"),
+            n => format!("similar (within {n} bits)"),
+        };
+        writeln!(
+            handle,
+            "{header}\n================================================================"
+        )?;
+
+        for path in group.keepers() {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+        writeln!(
+            handle,
+            "---------------------------- similar ---------------------------"
+        )?;
+        for path in duplicates {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+        writeln!(handle)?;
+    }
+
+    writeln!(
+        handle,
+        "{count} similar files ({})",
+        size.fmt_size(Conventional)
+    )?;
+    Ok(())
+}
+
+pub fn pretty_print_archived(scan: &Scan, style: PathStyle) -> io::Result<()> {
+    if scan.archived.is_empty() {
+        return Ok(());
+    }
+
+    let mut handle = io::stdout().lock();
+    let mut count = 0;
+    let mut size = 0;
+
+    writeln!(handle)?;
+    for group in &scan.archived {
+        count += group.duplicates().len();
+        size += group.duplicates().len() as u64 * group.len;
+
+        writeln!(
+            handle,
+            "archived\n================================================================"
+        )?;
+
+        // Members are named for the archive holding them, which lies under a root like any file.
+        for path in group.members.iter().chain(group.keepers()) {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+        writeln!(
+            handle,
+            "-------------------------- duplicates --------------------------"
+        )?;
+        for path in group.duplicates() {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+        writeln!(handle)?;
+    }
+
+    writeln!(
+        handle,
+        "{count} files duplicated in archives ({})",
+        size.fmt_size(Conventional)
+    )?;
+    Ok(())
+}
+
+pub fn pretty_print_directories(scan: &DirectoryScan, style: PathStyle) -> io::Result<()> {
+    let mut handle = io::stdout().lock();
+    let mut count = 0;
+    let mut size = 0;
+
+    for group in &scan.groups {
+        let duplicates = group.duplicates().len();
+        count += duplicates;
+        size += duplicates as u64 * group.len;
+
+        let files = match group.files {
+            1 => String::from("1 file"),
+            n => format!("{n} files"),
+        };
+        writeln!(
+            handle,
+            "{files} ({})\n================================================================",
+            group.len.fmt_size(Conventional)
+        )?;
+
+        for path in group.keepers() {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+        writeln!(
+            handle,
+            "-------------------------- duplicates --------------------------"
+        )?;
+        for path in group.duplicates() {
+            writeln!(handle, "{}", format_path(scan.root_of(path), path, style))?;
+        }
+        writeln!(handle)?;
+    }
+
+    if !scan.subsets.is_empty() {
+        writeln!(
+            handle,
+            "subsets\n================================================================"
+        )?;
+        for subset in &scan.subsets {
+            writeln!(
+                handle,
+                "{} is within {}",
+                format_path(scan.root_of(&subset.dir), &subset.dir, style),
+                format_path(scan.root_of(&subset.of), &subset.of, style)
+            )?;
+        }
+        writeln!(handle)?;
+    }
+
+    writeln!(
+        handle,
+        "{count} duplicate directories ({})",
+        size.fmt_size(Conventional)
+    )?;
+    Ok(())
+}
+
+pub fn pretty_print_empty_dirs(
+    dirs: &[PathBuf],
+    roots: &[&Path],
+    style: PathStyle,
+) -> io::Result<()> {
+    let mut handle = io::stdout().lock();
+    let mut sorted: Vec<_> = dirs.iter().collect();
+    sorted.sort();
+
+    for path in sorted {
+        let root = roots.iter().copied().find(|root| path.starts_with(root));
+        writeln!(handle, "{}", format_path(root, path, style))?;
+    }
+
+    writeln!(handle, "{} empty directories", dirs.len())?;
+    Ok(())
+}
+
+pub fn pretty_print_shared_dirs(scan: &Scan, style: PathStyle) -> io::Result<()> {
+    let mut handle = io::stdout().lock();
+    let pairs = SharedDirs::find(scan);
+
+    for pair in &pairs {
+        let files = match pair.files {
+            1 => String::from("1 shared file"),
+            n => format!("{n} shared files"),
+        };
+        writeln!(
+            handle,
+            "{files} ({})\n================================================================",
+            pair.bytes.fmt_size(Conventional)
+        )?;
+        for dir in &pair.dirs {
+            writeln!(handle, "{}", report::format_dir(scan, dir, style))?;
+        }
+        writeln!(handle)?;
+    }
+
+    writeln!(handle, "{} pairs of directories", pairs.len())?;
+    Ok(())
+}
+
+/// Print statistics for a scan, along with the time taken to remove duplicates, if they were.
+pub fn pretty_print_stats(
+    scan: &Scan,
+    style: PathStyle,
+    action_time: Option<Duration>,
+) -> io::Result<()> {
+    let mut handle = io::stdout().lock();
+    let summary = Summary::new(scan, TOP);
+    let stats = &scan.stats;
+
+    writeln!(
+        handle,
+        "\nstats\n================================================================"
+    )?;
+    writeln!(
+        handle,
+        "files scanned     {} ({})",
+        stats.files,
+        stats.bytes.fmt_size(Conventional)
+    )?;
+    writeln!(
+        handle,
+        "size candidates   {} ({})",
+        stats.candidates,
+        stats.candidate_bytes.fmt_size(Conventional)
+    )?;
+    writeln!(handle, "groups            {}", summary.groups)?;
+    writeln!(
+        handle,
+        "duplicates        {} ({})",
+        summary.duplicates,
+        summary.reclaimable.fmt_size(Conventional)
+    )?;
+    writeln!(handle, "walk time         {:.2?}", stats.walk_time)?;
+    writeln!(handle, "hash time         {:.2?}", stats.hash_time)?;
+    if let Some(action_time) = action_time {
+        writeln!(handle, "action time       {action_time:.2?}")?;
+    }
+
+    if !summary.distribution.is_empty() {
+        writeln!(handle, "\nfiles per group")?;
+        for (files, groups) in &summary.distribution {
+            writeln!(handle, "  {files:<15} {groups} groups")?;
+        }
+    }
+
+    if !summary.largest.is_empty() {
+        writeln!(handle, "\nlargest groups")?;
+        for &idx in &summary.largest {
+            let group = &scan.groups[idx];
+            writeln!(
+                handle,
+                "  {}  {} files, {} reclaimable",
+                group.imprint,
+                group.files.len(),
+                report::reclaimable(scan, idx).fmt_size(Conventional)
+            )?;
+        }
+    }
+
+    if !summary.directories.is_empty() {
+        writeln!(handle, "\ntop directories")?;
+        for (dir, count, bytes) in &summary.directories {
+            writeln!(
+                handle,
+                "  {}  {count} duplicates, {}",
+                report::format_dir(scan, dir, style),
+                bytes.fmt_size(Conventional)
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Explain why the last file kept was preferred over the first duplicate. When comparing, the
+/// first tier holds the root paths and the second the compare paths; otherwise, each tier is a
+/// root in priority order.
+fn write_explanation(
+    mut handle: impl Write,
+    scan: &Scan,
+    group: &Group,
+    scanner: &Scanner,
+    comparing: bool,
+) -> io::Result<()> {
+    let (Some(kept), Some(next)) = (group.keepers().last(), group.duplicates().first()) else {
+        return Ok(());
+    };
+
+    let (kept_tier, next_tier) = (scan.tier_of(kept), scan.tier_of(next));
+    if scan.tiers.len() > 1 && kept_tier != next_tier {
+        let reason = if comparing {
+            "found in the root path"
+        } else {
+            "found in a higher-priority root"
+        };
+        return writeln!(handle, "why: {reason}");
+    }
+
+    let reasons = scanner.explain(kept, next, &scan.cache);
+    match reasons.last() {
+        Some(reason) if reason.ordering.is_ne() => writeln!(handle, "why: {}", reason.rule)?,
+        _ => writeln!(handle, "why: every rule tied; first by path")?,
+    }
+
+    for reason in reasons {
+        let verdict = if reason.ordering.is_eq() {
+            "tie"
+        } else {
+            "decided"
+        };
+
+        if reason.a.is_empty() && reason.b.is_empty() {
+            writeln!(handle, "  {} ({verdict})", reason.rule)?;
+        } else if reason.a == reason.b {
+            writeln!(handle, "  {} ({verdict}): {}", reason.rule, reason.a)?;
+        } else {
+            writeln!(
+                handle,
+                "  {} ({verdict}): {} vs {}",
+                reason.rule, reason.a, reason.b
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn format_path<'a>(root: Option<&Path>, path: &'a Path, style: PathStyle) -> Cow<'a, str> {
+    let name = || {
+        path.file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| path.to_string_lossy())
+    };
+
+    match style {
+        PathStyle::AsFound => path.to_string_lossy(),
+        PathStyle::Absolute => path
+            .canonicalize()
+            .map(|path| Cow::from(path.to_string_lossy().into_owned()))
+            .unwrap_or_else(|_| path.to_string_lossy()),
+
+        // A root may itself be a file, in which case there is nothing left of the path once the
+        // root is stripped from it.
+        PathStyle::Relative => root
+            .and_then(|root| path.strip_prefix(root).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .map(|relative| relative.to_string_lossy())
+            .unwrap_or_else(name),
+        PathStyle::Name => name(),
+    }
+}
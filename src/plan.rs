@@ -0,0 +1,423 @@
+use std::{
+    cmp::Reverse,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{dirs::DirectoryScan, scan::Scan};
+
+/// A change to be made to the file system.
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Remove a duplicate file of the given length.
+    Remove {
+        /// The file to remove.
+        path: PathBuf,
+
+        /// The file's length, in bytes, as found by the scan.
+        len: u64,
+    },
+
+    /// Remove a directory, once the files in it have been removed. A directory still holding
+    /// anything which wasn't examined, such as hidden or ignored files, is left alone.
+    RemoveDirectory {
+        /// The directory to remove.
+        path: PathBuf,
+    },
+}
+
+/// What executing a plan removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Removed {
+    /// The number of files removed.
+    pub files: usize,
+
+    /// The combined length of the files removed.
+    pub bytes: u64,
+
+    /// The number of directories removed. Directories found not to be empty are left alone, and
+    /// not counted.
+    pub dirs: usize,
+}
+
+/// The set of actions required to deduplicate a scan.
+///
+/// Building a plan has no effect on the file system; nothing happens until the plan is executed.
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    /// The changes to make, in the order they are to be made.
+    pub actions: Vec<Action>,
+
+    /// The paths to be kept, one set for each group. No action may touch any of them, and at
+    /// least one path in each set must still be readable once the plan has been executed.
+    pub keep: Vec<Vec<PathBuf>>,
+}
+
+impl Plan {
+    /// Plan the removal of every duplicate in the scan, including loose copies of files found
+    /// in archives. Where no loose copy of an archived file is to be kept, a loose file is removed
+    /// even if it would otherwise have been kept, and the archive is kept in its place.
+    pub fn new(scan: &Scan) -> Self {
+        let mut planned = HashSet::new();
+        let actions = scan
+            .groups
+            .iter()
+            .flat_map(|group| group.duplicates())
+            .chain(scan.archived.iter().flat_map(|group| group.duplicates()))
+            .filter(|&path| planned.insert(path))
+            .map(|path| Action::Remove {
+                path: path.clone(),
+                len: scan.cache.get(path).map(|x| x.len).unwrap_or_default(),
+            })
+            .collect();
+
+        let mut archives: HashMap<&PathBuf, Vec<&PathBuf>> = HashMap::new();
+        for group in &scan.archived {
+            for path in group.duplicates() {
+                archives.entry(path).or_default().extend(&group.archives);
+            }
+        }
+
+        let keep = scan
+            .groups
+            .iter()
+            .map(|group| {
+                group
+                    .keepers()
+                    .iter()
+                    .flat_map(|path| {
+                        // A loose file removed in favour of an archived copy.
+                        if planned.contains(path) {
+                            archives.get(path).cloned().unwrap_or_default()
+                        } else {
+                            vec![path]
+                        }
+                    })
+                    .filter(|path| !planned.contains(path))
+                    .cloned()
+                    .collect()
+            })
+            .chain(scan.archived.iter().map(|group| {
+                group
+                    .keepers()
+                    .iter()
+                    .chain(&group.archives)
+                    .filter(|path| !planned.contains(path))
+                    .cloned()
+                    .collect()
+            }))
+            .collect();
+
+        Self { actions, keep }
+    }
+
+    /// Plan the removal of every duplicate directory in a directory scan: first the files in it,
+    /// then the directory itself and its subdirectories, deepest first.
+    ///
+    /// Groups may overlap, where a duplicate directory lies within another group's directory. A
+    /// directory within one already planned for removal, or within one to be kept, is left to
+    /// that directory.
+    pub fn for_directories(scan: &DirectoryScan) -> Self {
+        let keepers: HashSet<&Path> = scan
+            .groups
+            .iter()
+            .flat_map(|group| group.keepers())
+            .map(PathBuf::as_path)
+            .collect();
+
+        let mut dirs: Vec<&PathBuf> = scan
+            .groups
+            .iter()
+            .flat_map(|group| group.duplicates())
+            .collect();
+        dirs.sort_by_key(|dir| dir.components().count());
+
+        let mut planned: HashSet<&Path> = HashSet::new();
+        let mut actions = Vec::new();
+        for dir in dirs {
+            let covered = dir
+                .ancestors()
+                .skip(1)
+                .any(|dir| planned.contains(dir) || keepers.contains(dir));
+            let Some(contents) = scan.contents.get(dir).filter(|_| !covered) else {
+                continue;
+            };
+            if !planned.insert(dir) {
+                continue;
+            }
+
+            actions.extend(contents.files.iter().map(|(path, len)| Action::Remove {
+                path: path.clone(),
+                len: *len,
+            }));
+            actions.extend(
+                contents
+                    .dirs
+                    .iter()
+                    .map(|path| Action::RemoveDirectory { path: path.clone() }),
+            );
+        }
+
+        // A group's keeper may lie within a directory removed as a copy of another, in which case
+        // whichever of its directories remain are kept.
+        let keep = scan
+            .groups
+            .iter()
+            .map(|group| {
+                group
+                    .dirs
+                    .iter()
+                    .filter(|dir| !dir.ancestors().any(|dir| planned.contains(dir)))
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+
+        Self { actions, keep }
+    }
+
+    /// Plan the removal of empty directories, which should be listed deepest first.
+    pub fn for_empty_dirs(dirs: &[PathBuf]) -> Self {
+        let actions = dirs
+            .iter()
+            .map(|path| Action::RemoveDirectory { path: path.clone() })
+            .collect();
+
+        Self {
+            actions,
+            keep: Vec::new(),
+        }
+    }
+
+    /// Also plan the removal of every file resembling a file to be kept. Files which merely look
+    /// alike are never removed unless asked for.
+    ///
+    /// A file kept from a group of exact duplicates may itself resemble a better file. It is then
+    /// removed along with the other files kept from its group, and the group is kept by the file
+    /// it resembles instead. Files kept in place of archived copies are never removed.
+    pub fn include_similar(mut self, scan: &Scan) -> Self {
+        let exact = scan.groups.len();
+        let held: HashSet<PathBuf> = self.keep[exact..].iter().flatten().cloned().collect();
+        let kept_by: HashMap<PathBuf, usize> = self.keep[..exact]
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, keep)| keep.iter().map(move |path| (path.clone(), idx)))
+            .collect();
+
+        let mut moved = HashSet::new();
+        for group in &scan.similar {
+            for path in group.duplicates() {
+                if held.contains(path) {
+                    continue;
+                }
+
+                let removed = match kept_by.get(path) {
+                    None => vec![path.clone()],
+                    Some(&idx) if moved.insert(idx) => {
+                        let files = &scan.groups[idx].files;
+                        let (removed, kept): (Vec<_>, Vec<_>) = self.keep[idx]
+                            .drain(..)
+                            .partition(|path| files.contains(path) && !held.contains(path));
+                        self.keep[idx] = group.keepers().iter().cloned().chain(kept).collect();
+                        removed
+                    }
+                    // Already removed along with the rest of its group.
+                    Some(_) => continue,
+                };
+
+                self.actions
+                    .extend(removed.into_iter().map(|path| Action::Remove {
+                        len: scan.cache.get(&path).map(|x| x.len).unwrap_or_default(),
+                        path,
+                    }));
+            }
+        }
+
+        self.keep
+            .extend(scan.similar.iter().map(|group| group.keepers().to_vec()));
+        self
+    }
+
+    /// Carry out each action in turn, stopping at the first failure. Returns what was removed.
+    ///
+    /// Nothing is done if any action would touch a path to be kept, or if any group has nothing
+    /// left to keep. Once every action has been carried out, each group is checked again, and an
+    /// error is returned if one has lost its last readable copy.
+    pub fn execute(&self) -> io::Result<Removed> {
+        let kept: HashSet<&Path> = self.keep.iter().flatten().map(PathBuf::as_path).collect();
+        for action in &self.actions {
+            let (Action::Remove { path, .. } | Action::RemoveDirectory { path }) = action;
+            if let Some(keeper) = path.ancestors().find(|dir| kept.contains(dir)) {
+                return Err(io::Error::other(format!(
+                    "refusing to remove {}, which would remove {}, a file to be kept",
+                    path.display(),
+                    keeper.display()
+                )));
+            }
+        }
+        self.check_survivors()?;
+
+        let mut removed = Removed::default();
+        for action in &self.actions {
+            match action {
+                Action::Remove { path, len } => {
+                    fs::remove_file(path)?;
+                    removed.files += 1;
+                    removed.bytes += len;
+                }
+                Action::RemoveDirectory { path } => match fs::remove_dir(path) {
+                    Ok(()) => removed.dirs += 1,
+                    Err(err) if err.kind() != io::ErrorKind::DirectoryNotEmpty => return Err(err),
+                    Err(_) => {}
+                },
+            }
+        }
+
+        self.check_survivors()?;
+        Ok(removed)
+    }
+
+    /// Make sure every group has at least one path to be kept which can still be read.
+    fn check_survivors(&self) -> io::Result<()> {
+        match self
+            .keep
+            .iter()
+            .find(|keep| !keep.iter().any(|x| is_readable(x)))
+        {
+            Some(keep) => Err(io::Error::other(format!(
+                "no readable copy left of a group kept as {}",
+                keep.iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Once the plan has been executed, remove any directory it left empty beneath one of the
+    /// roots, working upward from each removed path. Returns the number of directories removed.
+    ///
+    /// Only directories above something the plan removed are considered, so a directory which
+    /// was empty to begin with is never removed, nor is a root. Directories still holding
+    /// anything, such as hidden or ignored files, are left alone.
+    pub fn prune_empty_dirs(&self, roots: &[PathBuf]) -> io::Result<usize> {
+        let is_beneath_root = |dir: &Path| {
+            roots
+                .iter()
+                .any(|root| dir.starts_with(root) && dir != root.as_path())
+        };
+
+        let mut dirs: Vec<_> = self
+            .actions
+            .iter()
+            .flat_map(|action| {
+                let (Action::Remove { path, .. } | Action::RemoveDirectory { path }) = action;
+                path.ancestors()
+                    .skip(1)
+                    .take_while(|&dir| is_beneath_root(dir))
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        // Children come before their parents, so that a parent is only tried once everything
+        // beneath it has been.
+        dirs.sort_by_key(|dir| Reverse(dir.components().count()));
+
+        let mut count = 0;
+        for dir in dirs {
+            match fs::remove_dir(dir) {
+                Ok(()) => count += 1,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::DirectoryNotEmpty | io::ErrorKind::NotFound
+                    ) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Whether a path can be read: a directory by listing it, and a file by reading from it.
+fn is_readable(path: &Path) -> bool {
+    if path.is_dir() {
+        return fs::read_dir(path).is_ok();
+    }
+
+    File::open(path).is_ok_and(|mut file| file.read(&mut [0]).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Action, Plan};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn pruning_removes_only_directories_emptied_by_the_plan() {
+        let root = TempDir::new("prune");
+        for dir in ["emptied/nested", "hidden", "already-empty"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("emptied/nested/copy.txt"), "copy").unwrap();
+        fs::write(root.join("hidden/copy.txt"), "copy").unwrap();
+        fs::write(root.join("hidden/.marker"), "").unwrap();
+
+        let plan = Plan {
+            actions: ["emptied/nested/copy.txt", "hidden/copy.txt"]
+                .into_iter()
+                .map(|path| Action::Remove {
+                    path: root.join(path),
+                    len: 4,
+                })
+                .collect(),
+            keep: vec![vec![root.join("kept.txt")]],
+        };
+        fs::write(root.join("kept.txt"), "copy").unwrap();
+        plan.execute().unwrap();
+        let pruned = plan.prune_empty_dirs(&[root.to_path_buf()]).unwrap();
+
+        let mut left: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+
+        assert_eq!(pruned, 2);
+        assert_eq!(left, ["already-empty", "hidden", "kept.txt"]);
+    }
+
+    #[test]
+    fn keepers_are_never_removed() {
+        let root = TempDir::new("keep");
+        for name in ["a-1", "a-2"] {
+            fs::write(root.join(name), "aaaa").unwrap();
+        }
+
+        let remove = |name: &str| Action::Remove {
+            path: root.join(name),
+            len: 4,
+        };
+        let targets_keeper = Plan {
+            actions: vec![remove("a-2"), remove("a-1")],
+            keep: vec![vec![root.join("a-1")]],
+        };
+        let keeper_missing = Plan {
+            actions: vec![remove("a-2")],
+            keep: vec![vec![root.join("a-3")]],
+        };
+        let refused = [targets_keeper.execute(), keeper_missing.execute()];
+        let left = fs::read_dir(&root).unwrap().count();
+
+        assert!(refused.iter().all(Result::is_err));
+        assert_eq!(left, 2);
+    }
+}
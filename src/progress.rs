@@ -0,0 +1,161 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use fmtsize::{Conventional, FmtSize};
+
+use crate::stats::Stats;
+
+/// Redrawing on every file makes the terminal the bottleneck for large trees.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A single status line on stderr describing how far along a scan is.
+///
+/// Progress is only displayed when enabled and stderr is a terminal; otherwise, nothing is drawn,
+/// though the [`Stats`] for the scan are still recorded.
+#[derive(Debug)]
+pub struct Progress {
+    enabled: bool,
+    last_draw: Option<Instant>,
+    hash_started: Option<Instant>,
+    walked: usize,
+    candidates: usize,
+    hashed: u64,
+    total: u64,
+    started: Instant,
+    stats: Stats,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && io::stderr().is_terminal(),
+            last_draw: None,
+            hash_started: None,
+            walked: 0,
+            candidates: 0,
+            hashed: 0,
+            total: 0,
+            started: Instant::now(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Record a file seen during the walk.
+    pub fn walked(&mut self) {
+        self.walked += 1;
+        self.draw(false);
+    }
+
+    /// Record a file accepted for examination, once the walk has found it and filters have
+    /// passed it.
+    pub fn examined(&mut self, len: u64) {
+        self.stats.files += 1;
+        self.stats.bytes += len;
+    }
+
+    /// Record the result of bucketing by size: the number of files which will need to be hashed
+    /// and their combined length. May be called again to begin another round of hashing.
+    pub fn candidates(&mut self, count: usize, bytes: u64) {
+        // Only the first round follows the walk and bucketing by size.
+        if self.hash_started.is_none() {
+            self.stats.candidates = count;
+            self.stats.candidate_bytes = bytes;
+            self.stats.walk_time = self.started.elapsed();
+        }
+
+        self.candidates = count;
+        self.hashed = 0;
+        self.total = bytes;
+        self.hash_started = Some(Instant::now());
+        self.draw(true);
+    }
+
+    /// Record a file having been hashed.
+    pub fn hashed(&mut self, len: u64) {
+        self.hashed += len;
+        self.draw(false);
+    }
+
+    /// What was recorded about the scan so far, including how long each phase took.
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        match self.hash_started {
+            Some(_) => stats.hash_time = self.started.elapsed() - stats.walk_time,
+            None => stats.walk_time = self.started.elapsed(),
+        }
+        stats
+    }
+
+    /// Clear the status line so that it doesn't get tangled up with regular output.
+    pub fn finish(&mut self) {
+        if self.enabled && self.last_draw.is_some() {
+            let _ = write!(io::stderr().lock(), "\r\x1b[2K");
+            self.last_draw = None;
+        }
+    }
+
+    fn draw(&mut self, force: bool) {
+        if !self.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        if !force
+            && self
+                .last_draw
+                .is_some_and(|last| now.duration_since(last) < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(now);
+
+        let line = match self.hash_started {
+            None => format!("walked {} files", self.walked),
+            Some(started) => {
+                let elapsed = now.duration_since(started).as_secs_f64();
+                let rate = if elapsed > 0.0 {
+                    (self.hashed as f64 / elapsed) as u64
+                } else {
+                    0
+                };
+                let eta = self
+                    .total
+                    .saturating_sub(self.hashed)
+                    .checked_div(rate)
+                    .map(format_eta)
+                    .unwrap_or_else(|| String::from("--:--"));
+
+                format!(
+                    "walked {} files | {} candidates | hashed {} / {} | {}/s | ETA {}",
+                    self.walked,
+                    self.candidates,
+                    self.hashed.fmt_size(Conventional),
+                    self.total.fmt_size(Conventional),
+                    rate.fmt_size(Conventional),
+                    eta,
+                )
+            }
+        };
+
+        let mut handle = io::stderr().lock();
+        let _ = write!(handle, "\r\x1b[2K{line}");
+        let _ = handle.flush();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn format_eta(seconds: u64) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
@@ -1,57 +1,242 @@
-use std::path::Path;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+    sync::Arc,
+};
 
 use hashbrown::HashSet;
 use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
 
+use crate::token::tokenize;
+
+/// How descriptive a path appears to be. Greater ranks are preferred.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Rank {
     segments: i32,
     words: i32,
-    is_unique: bool,
+    penalty: Reverse<u32>,
 }
 
-pub struct PathRanker {
-    dictionary: HashSet<&'static str>,
+impl Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "segments {}, words {}, penalty {}",
+            self.segments, self.words, self.penalty.0
+        )
+    }
+}
+
+/// Copy markers recognized by every ranker, with their weights, and a pattern for segments which
+/// only look as though they hold the marker.
+///
+/// Each is applied in turn to what remains of a path segment once the markers before it have been
+/// removed, so a marker is only counted once even where several patterns would match it.
+static COPY_MARKERS: &[(&str, u32, Option<&str>)] = &[
+    // hello (conflicted copy 2024-01-01).jpg, hello (Bob's conflicted copy).jpg
+    (r"(?i)\s*\([^)]*conflicted copy[^)]*\)", 2, None),
+    // Copy of hello.jpg, Copy (2) of hello.jpg
+    (r"(?i)^copy(?:\s*\(\d+\))? of\s+", 2, None),
+    // hello - Copy.jpg, hello - Copy (2).jpg, hello_copy2.jpg, hello copy 2.jpg, but not
+    // photo_copyright.jpg
+    (
+        r"(?i)(?:\s*-\s*|[\s_])copy(?:\s*\d+|\s*\(\d+\)|\b)",
+        2,
+        None,
+    ),
+    // hello (1).jpg
+    (r"\s*\(\d+\)", 1, None),
+    // hello.jpg~, hello.jpg.bak
+    (r"~$", 1, None),
+    (r"(?i)\.(?:bak|old|orig)$", 1, None),
+    // IMG_0001-1.jpg, but not 2024-01-15.pdf
+    (
+        r"-\d{1,2}(?:\.[^.\s]+)?$",
+        1,
+        Some(r"(?:^|\D)(?:19|20)\d{2}-\d{2}(?:-\d{2})?(?:\.[^.\s]+)?$"),
+    ),
+];
+
+/// A pattern marking a path as a likely copy, and how heavily to weigh it.
+#[derive(Clone, Debug)]
+struct Penalty {
     pattern: Regex,
+    weight: u32,
+
+    /// Segments matching this are never penalized, though they match the pattern.
+    unless: Option<Regex>,
+}
+
+/// Prefers the most descriptive path: the one nested most deeply, with the most dictionary words,
+/// and without copy markers like `(1)` or `- Copy` in its name.
+///
+/// Words are drawn from the bundled English word list unless the ranker is created with
+/// [`PathRanker::empty`]. Either way, more can be added from word lists loaded at runtime.
+/// Likewise, further copy markers can be added to the built-in set with
+/// [`PathRanker::penalize`].
+///
+/// The dictionary is shared between clones, so a ranker is cheap to clone once built.
+#[derive(Clone)]
+pub struct PathRanker {
+    dictionary: Arc<HashSet<Cow<'static, str>>>,
+    penalties: Vec<Penalty>,
 }
 
 impl PathRanker {
+    /// A ranker knowing the words of the bundled English word list.
     pub fn new() -> Self {
         let words = include_str!("../resource/enable1.txt");
         Self {
-            dictionary: words.split_whitespace().collect(),
-            pattern: Regex::new(r#"\(\d+\)"#).unwrap(),
+            dictionary: Arc::new(words.split_whitespace().map(Cow::Borrowed).collect()),
+            ..Self::empty()
+        }
+    }
+
+    /// A ranker that knows no words at all, for use with word lists of your own.
+    pub fn empty() -> Self {
+        let penalties = COPY_MARKERS
+            .iter()
+            .map(|&(pattern, weight, unless)| Penalty {
+                pattern: Regex::new(pattern).unwrap(),
+                weight,
+                unless: unless.map(|unless| Regex::new(unless).unwrap()),
+            })
+            .collect();
+
+        Self {
+            dictionary: Arc::default(),
+            penalties,
         }
     }
 
+    /// Add words, separated by whitespace, to the dictionary.
+    pub fn words(mut self, words: &str) -> Self {
+        Arc::make_mut(&mut self.dictionary).extend(
+            words
+                .split_whitespace()
+                .map(|word| Cow::Owned(word.nfc().collect::<String>().to_lowercase())),
+        );
+        self
+    }
+
+    /// Add the words from a word list on disk, separated by whitespace (typically one per line).
+    pub fn dictionary(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let words = fs::read_to_string(path)?;
+        Ok(self.words(&words))
+    }
+
+    /// Mark path segments matching this pattern as copies. Each match adds `weight` to a path's
+    /// penalty; among paths otherwise equally descriptive, the one with the least penalty wins.
+    ///
+    /// Patterns are applied after the built-in copy markers, and matched text does not count
+    /// toward a path's words.
+    pub fn penalize(mut self, pattern: &str, weight: u32) -> Result<Self, regex::Error> {
+        self.penalties.push(Penalty {
+            pattern: Regex::new(pattern)?,
+            weight,
+            unless: None,
+        });
+        Ok(self)
+    }
+
+    /// Evaluate how descriptive a path is.
     pub fn rank(&self, path: impl AsRef<Path>) -> Rank {
         let path = path.as_ref();
-        let utf8_segments = path.components().filter_map(|x| x.as_os_str().to_str());
+        let mut penalty = 0;
+        let segments: Vec<_> = path
+            .components()
+            .map(|x| {
+                let segment: String = x.as_os_str().to_string_lossy().nfc().collect();
+                let (segment, weight) = self.strip_copy_markers(&segment);
+                penalty += weight;
+                segment.into_owned()
+            })
+            .collect();
 
-        let words = self.evaluate_segments(utf8_segments);
+        let words = self.evaluate_segments(segments.iter().map(AsRef::as_ref));
 
         Rank {
             segments: path.components().count() as i32,
             words,
-            is_unique: !path
-                .file_name()
-                .and_then(|file_name| file_name.to_str())
-                .map(|file_name| self.pattern.is_match(file_name))
-                .unwrap_or_default(),
+            penalty: Reverse(penalty),
         }
     }
 
+    /// Remove any copy markers from a path segment, returning what remains along with the total
+    /// weight of the markers removed.
+    fn strip_copy_markers<'a>(&self, segment: &'a str) -> (Cow<'a, str>, u32) {
+        self.penalties
+            .iter()
+            .fold((Cow::Borrowed(segment), 0), |(segment, total), penalty| {
+                let matches = penalty.pattern.find_iter(&segment).count() as u32;
+                let excepted = || {
+                    penalty
+                        .unless
+                        .as_ref()
+                        .is_some_and(|unless| unless.is_match(&segment))
+                };
+                if matches == 0 || excepted() {
+                    return (segment, total);
+                }
+
+                let stripped = penalty.pattern.replace_all(&segment, "").into_owned();
+                (Cow::Owned(stripped), total + matches * penalty.weight)
+            })
+    }
+
     fn evaluate_segments<'a>(&self, segments: impl IntoIterator<Item = &'a str>) -> i32 {
-        static NON_WHITESPACE_SPLIT_CHARS: &[char] = &['.', '_', '-'];
+        segments
+            .into_iter()
+            .flat_map(tokenize)
+            .map(|token| {
+                let text = token.text.to_lowercase();
+                if token.unspaced {
+                    self.count_unspaced_words(&text)
+                } else {
+                    self.dictionary.contains(text.as_str()) as i32
+                }
+            })
+            .sum()
+    }
 
-        let candidates = segments.into_iter().flat_map(|x| {
-            x.split(|u: char| u.is_whitespace() || NON_WHITESPACE_SPLIT_CHARS.contains(&u))
-        });
+    /// Count the words in text written without spaces, matching the longest dictionary word at
+    /// each position. Text containing no dictionary words counts as a single word, on the theory
+    /// that a name written this way is probably a descriptive one.
+    fn count_unspaced_words(&self, text: &str) -> i32 {
+        const LONGEST_WORD: usize = 8;
+
+        let chars: Vec<_> = text.char_indices().map(|(idx, _)| idx).collect();
+        let mut words = 0;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let longest = (i + 1..=(i + LONGEST_WORD).min(chars.len()))
+                .rev()
+                .find(|&j| {
+                    let end = chars.get(j).copied().unwrap_or(text.len());
+                    self.dictionary.contains(&text[chars[i]..end])
+                });
+
+            match longest {
+                Some(j) => {
+                    words += 1;
+                    i = j;
+                }
+                None => i += 1,
+            }
+        }
+
+        words.max(1)
+    }
+}
 
-        candidates
-            .map(|s| s.to_lowercase())
-            .filter(|x| self.dictionary.contains(x.as_str()))
-            .count() as i32
+impl Default for PathRanker {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -84,4 +269,74 @@ mod tests {
         let duplicate = dbg!(ranker.rank("hello (1).jpg"));
         assert!(original > duplicate);
     }
+
+    #[test]
+    fn common_copy_markers_rank_lower() {
+        let ranker = PathRanker::new();
+        let original = ranker.rank("hello.jpg");
+        for copy in [
+            "Copy of hello.jpg",
+            "hello - Copy.jpg",
+            "hello_copy2.jpg",
+            "hello.jpg~",
+            "hello.jpg.bak",
+            "hello (conflicted copy 2024-01-01).jpg",
+        ] {
+            assert!(original > ranker.rank(copy), "{copy}");
+        }
+
+        assert!(ranker.rank("IMG_0001.jpg") > ranker.rank("IMG_0001-1.jpg"));
+    }
+
+    #[test]
+    fn names_resembling_copy_markers_are_not_copies() {
+        let ranker = PathRanker::new();
+        for name in ["photo_copyright.jpg", "my copybook.txt", "2024-01-15.pdf"] {
+            let (_, penalty) = ranker.strip_copy_markers(name);
+            assert_eq!(penalty, 0, "{name}");
+        }
+    }
+
+    #[test]
+    fn heavier_penalties_rank_lower() {
+        let ranker = PathRanker::new().penalize(r"(?i)\bdraft\b", 3).unwrap();
+        let draft = ranker.rank("hello draft.jpg");
+        let numbered = ranker.rank("hello (1).jpg");
+        assert!(numbered > draft);
+    }
+
+    #[test]
+    fn camel_case_words_count_toward_rank() {
+        let ranker = PathRanker::new();
+        let a = ranker.rank("VacationPhotoBeach.jpg");
+        let b = ranker.rank("IMG_20240101.jpg");
+        assert!(a > b);
+    }
+
+    #[test]
+    fn words_are_normalized_before_lookup() {
+        // Composed and decomposed forms of "über".
+        let ranker = PathRanker::empty().words("\u{fc}ber");
+        let a = ranker.rank("U\u{308}ber.jpg");
+        let b = ranker.rank("1234.jpg");
+        assert!(a > b);
+    }
+
+    #[test]
+    fn unspaced_words_count_toward_rank() {
+        let ranker = PathRanker::empty().words("東京 旅行");
+        assert!(ranker.rank("東京旅行.jpg") > ranker.rank("東京.jpg"));
+        assert!(ranker.rank("写真.jpg") > ranker.rank("1234.jpg"));
+    }
+
+    #[test]
+    fn added_words_count_toward_rank() {
+        let path = "Urlaub Strand.jpg";
+        let english = PathRanker::new();
+        let german = PathRanker::new().words("urlaub\nStrand\n");
+        assert!(german.rank(path) > english.rank(path));
+
+        let empty = PathRanker::empty();
+        assert_eq!(empty.rank("hello world.jpg"), empty.rank("1234 5678.jpg"));
+    }
 }
@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+use yogi::{Scan, SharedDirs, Summary};
+
+use crate::{config::PathStyle, output::format_path};
+
+/// The number of groups and directories listed among the largest in statistics.
+pub const TOP: usize = 10;
+
+#[derive(Serialize)]
+struct Report {
+    groups: Vec<GroupReport>,
+    similar: Vec<SimilarReport>,
+    archived: Vec<ArchivedReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<StatsReport>,
+}
+
+#[derive(Serialize)]
+struct GroupReport {
+    imprint: String,
+    len: u64,
+    keep: Vec<String>,
+    duplicates: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SimilarReport {
+    distance: u32,
+    keep: Vec<String>,
+    duplicates: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ArchivedReport {
+    len: u64,
+    members: Vec<String>,
+    keep: Vec<String>,
+    duplicates: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct StatsReport {
+    files: usize,
+    bytes: u64,
+    candidates: usize,
+    candidate_bytes: u64,
+    groups: usize,
+    duplicates: usize,
+    reclaimable: u64,
+    walk_seconds: f64,
+    hash_seconds: f64,
+    largest_groups: Vec<LargestGroup>,
+    directories: Vec<DirectoryStats>,
+    distribution: BTreeMap<usize, usize>,
+}
+
+#[derive(Serialize)]
+struct LargestGroup {
+    imprint: String,
+    files: usize,
+    reclaimable: u64,
+}
+
+#[derive(Serialize)]
+struct DirectoryStats {
+    path: String,
+    duplicates: usize,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct SharedDirsReport {
+    dirs: [String; 2],
+    files: usize,
+    bytes: u64,
+}
+
+/// Write the directories sharing files as a JSON array.
+pub fn write_shared_dirs_json(
+    mut handle: impl Write,
+    scan: &Scan,
+    style: PathStyle,
+) -> io::Result<()> {
+    let report: Vec<_> = SharedDirs::find(scan)
+        .into_iter()
+        .map(|pair| SharedDirsReport {
+            dirs: pair.dirs.map(|dir| format_dir(scan, &dir, style)),
+            files: pair.files,
+            bytes: pair.bytes,
+        })
+        .collect();
+
+    serde_json::to_writer_pretty(&mut handle, &report)?;
+    writeln!(handle)?;
+    handle.flush()
+}
+
+/// Write the scan as a single JSON document, with statistics if asked for.
+pub fn write_json(
+    mut handle: impl Write,
+    scan: &Scan,
+    style: PathStyle,
+    stats: bool,
+) -> io::Result<()> {
+    let paths = |paths: &[std::path::PathBuf]| {
+        paths
+            .iter()
+            .map(|path| format_path(scan.root_of(path), path, style).into_owned())
+            .collect()
+    };
+    let len = |path: Option<&std::path::PathBuf>| {
+        path.and_then(|path| scan.cache.get(path))
+            .map(|meta| meta.len)
+            .unwrap_or_default()
+    };
+
+    let report = Report {
+        groups: scan
+            .groups
+            .iter()
+            .map(|group| GroupReport {
+                imprint: group.imprint.to_string(),
+                len: len(group.files.first()),
+                keep: paths(group.keepers()),
+                duplicates: paths(group.duplicates()),
+            })
+            .collect(),
+        similar: scan
+            .similar
+            .iter()
+            .map(|group| SimilarReport {
+                distance: group.distance,
+                keep: paths(group.keepers()),
+                duplicates: paths(group.duplicates()),
+            })
+            .collect(),
+        archived: scan
+            .archived
+            .iter()
+            .map(|group| ArchivedReport {
+                len: group.len,
+                members: paths(&group.members),
+                keep: paths(group.keepers()),
+                duplicates: paths(group.duplicates()),
+            })
+            .collect(),
+        stats: stats.then(|| stats_report(scan, style)),
+    };
+
+    serde_json::to_writer_pretty(&mut handle, &report)?;
+    writeln!(handle)?;
+    handle.flush()
+}
+
+fn stats_report(scan: &Scan, style: PathStyle) -> StatsReport {
+    let summary = Summary::new(scan, TOP);
+    let stats = &scan.stats;
+
+    StatsReport {
+        files: stats.files,
+        bytes: stats.bytes,
+        candidates: stats.candidates,
+        candidate_bytes: stats.candidate_bytes,
+        groups: summary.groups,
+        duplicates: summary.duplicates,
+        reclaimable: summary.reclaimable,
+        walk_seconds: stats.walk_time.as_secs_f64(),
+        hash_seconds: stats.hash_time.as_secs_f64(),
+        largest_groups: summary
+            .largest
+            .iter()
+            .map(|&idx| {
+                let group = &scan.groups[idx];
+                LargestGroup {
+                    imprint: group.imprint.to_string(),
+                    files: group.files.len(),
+                    reclaimable: reclaimable(scan, idx),
+                }
+            })
+            .collect(),
+        directories: summary
+            .directories
+            .iter()
+            .map(|(dir, duplicates, bytes)| DirectoryStats {
+                path: format_dir(scan, dir, style),
+                duplicates: *duplicates,
+                bytes: *bytes,
+            })
+            .collect(),
+        distribution: summary.distribution,
+    }
+}
+
+/// The space to be reclaimed by removing the duplicates in a group.
+pub fn reclaimable(scan: &Scan, idx: usize) -> u64 {
+    scan.groups[idx]
+        .duplicates()
+        .iter()
+        .filter_map(|path| scan.cache.get(path).map(|x| x.len))
+        .sum()
+}
+
+/// Format a directory, which may itself be a root, and so have nothing left relative to it.
+pub fn format_dir(scan: &Scan, dir: &Path, style: PathStyle) -> String {
+    match style {
+        PathStyle::Relative if scan.root_of(dir).is_some_and(|root| root == dir) => {
+            String::from(".")
+        }
+        _ => format_path(scan.root_of(dir), dir, style).into_owned(),
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::HashSet;
+use imprint::Imprint;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    meta::Meta,
+    plan::{Action, Plan},
+    scan::Scan,
+};
+
+/// The version of the saved plan format written by this build.
+const VERSION: u32 = 1;
+
+/// A plan saved to disk for review before it is applied.
+///
+/// Unlike a [`Plan`], a saved plan records what each file looked like when it was scanned: its
+/// length, modification time and imprint. Before anything is removed, each file is checked
+/// against that record, and anything which has changed in the meantime is refused. Files to be
+/// kept are checked too; a group with no file left to keep has nothing removed from it.
+///
+/// Saved plans hold exact duplicates only.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedPlan {
+    /// The version of the format in which the plan was written.
+    pub version: u32,
+
+    /// The roots examined by the scan, beneath which emptied directories may be pruned.
+    pub roots: Vec<PathBuf>,
+
+    /// The groups of identical files found by the scan.
+    pub groups: Vec<SavedGroup>,
+}
+
+/// A group of identical files, split into those to be kept and those to be removed. Entries may
+/// be moved from one list to the other, or deleted, before the plan is applied. A file is only
+/// removed if its imprint matches that of a file still kept in the same group.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedGroup {
+    /// The files to be kept.
+    pub keep: Vec<Record>,
+
+    /// The files to be removed.
+    pub remove: Vec<Record>,
+}
+
+/// A file as it was when scanned.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// The file's absolute path.
+    pub path: PathBuf,
+
+    /// The file's length, in bytes.
+    pub len: u64,
+
+    /// The modification time, in RFC 3339 format, if the file system records one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+
+    /// The file's imprint.
+    pub imprint: String,
+}
+
+/// A file which a saved plan would have removed, but which was refused.
+#[derive(Clone, Debug)]
+pub struct Stale {
+    /// The file refused.
+    pub path: PathBuf,
+
+    /// Why it was refused.
+    pub reason: StaleReason,
+}
+
+/// Why a file was refused.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StaleReason {
+    /// The file no longer exists, or can't be read.
+    Missing,
+
+    /// The file's length has changed.
+    Len,
+
+    /// The file's modification time has changed.
+    Modified,
+
+    /// The file's contents have changed, though its length and modification time have not.
+    Content,
+
+    /// None of the files to be kept in the file's group is still as it was.
+    NoKeeper,
+
+    /// The file's contents match none of the files to be kept in its group.
+    Unmatched,
+
+    /// The file is also listed among those to be kept.
+    Kept,
+
+    /// The file is listed to be removed more than once.
+    Repeated,
+}
+
+impl Display for StaleReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StaleReason::Missing => "missing",
+            StaleReason::Len => "length changed",
+            StaleReason::Modified => "modified since the scan",
+            StaleReason::Content => "contents changed",
+            StaleReason::NoKeeper => "no file to keep is unchanged",
+            StaleReason::Unmatched => "matches no file to keep",
+            StaleReason::Kept => "also listed to be kept",
+            StaleReason::Repeated => "listed more than once",
+        })
+    }
+}
+
+impl SavedPlan {
+    /// Record every group of duplicates in the scan. Paths are made absolute, so that the plan
+    /// may be applied from anywhere.
+    pub fn new(scan: &Scan) -> Self {
+        let absolute = |path: &PathBuf| path.canonicalize().unwrap_or_else(|_| path.clone());
+        let record = |path: &PathBuf, imprint: &Imprint| Record {
+            path: absolute(path),
+            len: scan.cache.get(path).map(|x| x.len).unwrap_or_default(),
+            modified: scan
+                .cache
+                .get(path)
+                .and_then(|x| x.modified)
+                .map(|modified| humantime::format_rfc3339_nanos(modified).to_string()),
+            imprint: imprint.to_string(),
+        };
+
+        let groups = scan
+            .groups
+            .iter()
+            .map(|group| SavedGroup {
+                keep: group
+                    .keepers()
+                    .iter()
+                    .map(|path| record(path, &group.imprint))
+                    .collect(),
+                remove: group
+                    .duplicates()
+                    .iter()
+                    .map(|path| record(path, &group.imprint))
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            version: VERSION,
+            roots: scan.tiers.iter().flatten().map(absolute).collect(),
+            groups,
+        }
+    }
+
+    /// Read a plan saved by [`SavedPlan::write`]. Plans written in another version of the format
+    /// are refused.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let plan: Self = serde_json::from_str(&text)?;
+        if plan.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported plan version {}", plan.version),
+            ));
+        }
+        Ok(plan)
+    }
+
+    /// Write the plan as JSON, for review and editing before it is applied.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text + "\n")
+    }
+
+    /// Check every file against its record, returning a plan to remove those which are
+    /// unchanged and identical to an unchanged file kept in their group, along with the files
+    /// refused. A file listed to be removed more than once is only removed for the first group
+    /// listing it.
+    pub fn validate(&self) -> (Plan, Vec<Stale>) {
+        let mut actions = Vec::new();
+        let mut keep = Vec::new();
+        let mut stale = Vec::new();
+        let mut planned = HashSet::new();
+        let kept: HashSet<&Path> = self
+            .groups
+            .iter()
+            .flat_map(|group| &group.keep)
+            .map(|record| record.path.as_path())
+            .collect();
+
+        for group in &self.groups {
+            let keepers: Vec<_> = group
+                .keep
+                .iter()
+                .filter(|record| record.check().is_ok())
+                .collect();
+            let imprints: HashSet<&str> = keepers
+                .iter()
+                .map(|record| record.imprint.as_str())
+                .collect();
+            let has_keeper = !keepers.is_empty();
+            for record in &group.remove {
+                let refusal = if kept.contains(record.path.as_path()) {
+                    Err(StaleReason::Kept)
+                } else if !has_keeper {
+                    Err(StaleReason::NoKeeper)
+                } else if !imprints.contains(record.imprint.as_str()) {
+                    Err(StaleReason::Unmatched)
+                } else {
+                    record.check()
+                }
+                .and_then(|()| {
+                    planned
+                        .insert(record.path.as_path())
+                        .then_some(())
+                        .ok_or(StaleReason::Repeated)
+                });
+
+                match refusal {
+                    Ok(()) => actions.push(Action::Remove {
+                        path: record.path.clone(),
+                        len: record.len,
+                    }),
+                    Err(reason) => stale.push(Stale {
+                        path: record.path.clone(),
+                        reason,
+                    }),
+                }
+            }
+
+            if has_keeper {
+                keep.push(keepers.iter().map(|record| record.path.clone()).collect());
+            }
+        }
+
+        (Plan { actions, keep }, stale)
+    }
+}
+
+impl Record {
+    /// Whether the file is still as it was recorded.
+    fn check(&self) -> Result<(), StaleReason> {
+        let meta: Meta = fs::metadata(&self.path)
+            .map_err(|_| StaleReason::Missing)?
+            .into();
+
+        if meta.len != self.len {
+            return Err(StaleReason::Len);
+        }
+
+        if let Some(modified) = &self.modified {
+            let recorded = humantime::parse_rfc3339(modified).ok();
+            if recorded.is_none() || recorded != meta.modified {
+                return Err(StaleReason::Modified);
+            }
+        }
+
+        let current = Imprint::new(&self.path).map_err(|_| StaleReason::Missing)?;
+        if current.to_string() != self.imprint {
+            return Err(StaleReason::Content);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{scan::Scanner, testing::TempDir};
+
+    use super::{SavedPlan, StaleReason};
+
+    #[test]
+    fn changed_files_are_refused() {
+        let root = TempDir::new("saved");
+        for name in ["a-1", "a-2", "a-3", "b-1", "b-2"] {
+            let content = if name.starts_with('a') {
+                "aaaa"
+            } else {
+                "bbbb"
+            };
+            fs::write(root.join(name), content).unwrap();
+        }
+
+        let scan = Scanner::new(&*root).scan().unwrap();
+        let saved = root.join("plan.json");
+        SavedPlan::new(&scan).write(&saved).unwrap();
+        let saved = SavedPlan::read(&saved).unwrap();
+
+        // Change one duplicate of a without touching its length or modification time, and remove
+        // the file to be kept from b.
+        let a = saved.groups.iter().find(|g| g.remove.len() == 2).unwrap();
+        let b = saved.groups.iter().find(|g| g.remove.len() == 1).unwrap();
+        let changed = &a.remove[0].path;
+        let modified = fs::metadata(changed).unwrap().modified().unwrap();
+        fs::write(changed, "AAAA").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(changed)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::remove_file(&b.keep[0].path).unwrap();
+
+        let (plan, stale) = saved.validate();
+        let mut reasons: Vec<_> = stale.iter().map(|x| x.reason.clone()).collect();
+        reasons.sort_by_key(|reason| reason.to_string());
+
+        assert_eq!(plan.actions.len(), 1);
+        assert_eq!(reasons, [StaleReason::Content, StaleReason::NoKeeper]);
+    }
+
+    #[test]
+    fn files_listed_twice_are_removed_once() {
+        let root = TempDir::new("repeated");
+        for name in ["a-1", "a-2"] {
+            fs::write(root.join(name), "aaaa").unwrap();
+        }
+
+        let scan = Scanner::new(&*root).scan().unwrap();
+        let mut saved = SavedPlan::new(&scan);
+        saved.groups.push(saved.groups[0].clone());
+
+        let (plan, stale) = saved.validate();
+        let removed = plan.execute().map(|removed| removed.files);
+
+        assert_eq!(removed.unwrap(), 1);
+        let reasons: Vec<_> = stale.iter().map(|x| x.reason.clone()).collect();
+        assert_eq!(reasons, [StaleReason::Repeated]);
+    }
+
+    #[test]
+    fn files_unlike_every_keeper_are_refused() {
+        let root = TempDir::new("unmatched");
+        for name in ["a-1", "a-2", "b-1", "b-2"] {
+            let content = if name.starts_with('a') {
+                "aaaa"
+            } else {
+                "bbbb"
+            };
+            fs::write(root.join(name), content).unwrap();
+        }
+
+        // Swap the files to be removed between the two groups.
+        let scan = Scanner::new(&*root).scan().unwrap();
+        let mut saved = SavedPlan::new(&scan);
+        let [a, b] = &mut saved.groups[..] else {
+            panic!("expected two groups");
+        };
+        std::mem::swap(&mut a.remove, &mut b.remove);
+
+        let (plan, stale) = saved.validate();
+        let reasons: Vec<_> = stale.iter().map(|x| x.reason.clone()).collect();
+
+        assert!(plan.actions.is_empty());
+        assert_eq!(reasons, [StaleReason::Unmatched, StaleReason::Unmatched]);
+    }
+}
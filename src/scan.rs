@@ -0,0 +1,492 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use imprint::Imprint;
+
+use crate::{
+    archive::{self, Archived},
+    dirs::{self, DirectoryScan},
+    empty,
+    meta::{Meta, Metacache},
+    multiple,
+    plan::Plan,
+    progress::Progress,
+    similar::{self, ImageHash},
+    single,
+    sort::{PathSorter, Reason, SortOrder, get_sorter},
+    stats::Stats,
+    text::TextHash,
+};
+
+type Filter = Box<dyn Fn(&Path, &Meta) -> bool>;
+
+/// Describes which files to examine and how to choose among duplicates.
+///
+/// With no compare paths, every file under the roots is compared with every other, and all but
+/// the preferred file in each group are duplicates. With compare paths, only files under the
+/// compare paths are considered duplicates, and only of files found under the roots.
+///
+/// In priority order, each root is ranked above the roots added after it, and a file under a
+/// higher-priority root is always kept over an identical file under a lower-priority root.
+pub struct Scanner {
+    pub(crate) roots: Vec<PathBuf>,
+    pub(crate) compare: Vec<PathBuf>,
+    pub(crate) recurse: bool,
+    pub(crate) ignore: Vec<PathBuf>,
+    pub(crate) dedupe_compare: bool,
+    pub(crate) priority_order: bool,
+    similar_images: Option<(ImageHash, u32)>,
+    similar_text: Option<(TextHash, u32)>,
+    pub(crate) subsets: bool,
+    archives: bool,
+    include_empty: bool,
+    order_groups: GroupOrder,
+    top: Option<usize>,
+    filters: Vec<Filter>,
+    sorter: Option<Box<dyn PathSorter>>,
+    progress: bool,
+}
+
+impl Scanner {
+    /// A scanner examining a single root path, recursively, with the default sort order. Further
+    /// roots may be added with [`Scanner::root`].
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            roots: vec![root.into()],
+            compare: Vec::new(),
+            recurse: true,
+            ignore: Vec::new(),
+            dedupe_compare: false,
+            priority_order: false,
+            similar_images: None,
+            similar_text: None,
+            subsets: false,
+            archives: false,
+            include_empty: false,
+            order_groups: GroupOrder::default(),
+            top: None,
+            filters: Vec::new(),
+            sorter: None,
+            progress: false,
+        }
+    }
+
+    /// Add another root to be examined alongside the first.
+    pub fn root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.roots.push(path.into());
+        self
+    }
+
+    /// Add a path whose files will be checked against the files in the roots.
+    pub fn compare(mut self, path: impl Into<PathBuf>) -> Self {
+        self.compare.push(path.into());
+        self
+    }
+
+    /// Whether files under the compare paths which duplicate one another, but nothing in the
+    /// roots, should be reported as duplicates. Defaults to false.
+    pub fn dedupe_compare(mut self, dedupe: bool) -> Self {
+        self.dedupe_compare = dedupe;
+        self
+    }
+
+    /// Whether each root should outrank those added after it. Defaults to false, in which case
+    /// all roots are examined as if they were one tree. Cannot be combined with compare paths.
+    pub fn priority_order(mut self, priority_order: bool) -> Self {
+        self.priority_order = priority_order;
+        self
+    }
+
+    /// Also look for images which look alike without being identical, such as the same photo
+    /// resized or re-encoded. Images are similar when their hashes differ by no more than
+    /// `max_distance` bits, out of 64.
+    ///
+    /// Similar images are reported apart from exact duplicates, in [`Scan::similar`], and are
+    /// not removed by [`Scan::plan`].
+    pub fn similar_images(mut self, hash: ImageHash, max_distance: u32) -> Self {
+        self.similar_images = Some((hash, max_distance));
+        self
+    }
+
+    /// Also look for text files whose contents are nearly the same. With [`TextHash::SimHash`],
+    /// files are similar when their hashes differ by no more than `max_distance` bits, out of
+    /// 64; with [`TextHash::Normalized`], they must be identical once whitespace is normalized.
+    ///
+    /// As with images, similar files are reported in [`Scan::similar`] and are not removed by
+    /// [`Scan::plan`].
+    pub fn similar_text(mut self, hash: TextHash, max_distance: u32) -> Self {
+        self.similar_text = Some((hash, max_distance));
+        self
+    }
+
+    /// Also look inside zip and tar archives (including `.tar.gz` and `.tar.zst`) for files
+    /// duplicated loose on disk. Defaults to false.
+    ///
+    /// Matches are reported in [`Scan::archived`]. Archives are never modified; only the loose
+    /// copies are removed by [`Scan::plan`].
+    pub fn archives(mut self, archives: bool) -> Self {
+        self.archives = archives;
+        self
+    }
+
+    /// Whether [`Scanner::scan_directories`] should also look for directories whose files are
+    /// all found in another directory. Finding these means reading every file, rather than only
+    /// those in directories which look alike. Defaults to false.
+    pub fn subsets(mut self, subsets: bool) -> Self {
+        self.subsets = subsets;
+        self
+    }
+
+    /// Whether to descend into subdirectories. Defaults to true.
+    pub fn recurse(mut self, recurse: bool) -> Self {
+        self.recurse = recurse;
+        self
+    }
+
+    /// Skip this directory and everything beneath it.
+    pub fn ignore(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ignore.push(path.into());
+        self
+    }
+
+    /// Whether to examine empty files. Defaults to false, since every empty file is identical to
+    /// every other, and empty files are often kept on purpose, as markers.
+    pub fn include_empty(mut self, include_empty: bool) -> Self {
+        self.include_empty = include_empty;
+        self
+    }
+
+    /// How to order the groups of identical files found by a scan. Defaults to
+    /// [`GroupOrder::Path`].
+    pub fn order_groups(mut self, order: GroupOrder) -> Self {
+        self.order_groups = order;
+        self
+    }
+
+    /// Keep only the first `top` groups of identical files, once ordered, so that only those are
+    /// reported or removed.
+    pub fn top(mut self, top: usize) -> Self {
+        self.top = Some(top);
+        self
+    }
+
+    /// Only examine files for which the filter returns true.
+    pub fn filter(mut self, filter: impl Fn(&Path, &Meta) -> bool + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Choose files to keep using the built-in rules, in order of precedence.
+    pub fn sort(self, sort: &[SortOrder]) -> Self {
+        self.sorter(Box::new(get_sorter(sort)))
+    }
+
+    /// Choose files to keep using a custom strategy.
+    pub fn sorter(mut self, sorter: Box<dyn PathSorter>) -> Self {
+        self.sorter = Some(sorter);
+        self
+    }
+
+    /// Display progress on stderr while scanning. Progress is never displayed when stderr is not
+    /// a terminal. Defaults to false.
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Examine the file system for duplicates.
+    pub fn scan(&self) -> io::Result<Scan> {
+        let mut progress = Progress::new(self.progress);
+        let mut scan = self.with_sorter(|sorter| {
+            let mut scan = if self.tiers().len() > 1 {
+                multiple::scan(self, sorter, &mut progress)?
+            } else {
+                single::scan(self, sorter, &mut progress)?
+            };
+
+            if let Some(images) = self.similar_images {
+                similar::images(self, &mut scan, sorter, images, &mut progress)?;
+            }
+
+            if let Some(text) = self.similar_text {
+                similar::text(self, &mut scan, sorter, text, &mut progress)?;
+            }
+
+            if self.archives {
+                archive::scan(self, &mut scan, sorter, &mut progress)?;
+            }
+
+            // Groups are only cut once the search for similar files has seen every duplicate.
+            scan.order_groups(self.order_groups);
+            if let Some(top) = self.top {
+                scan.groups.truncate(top);
+            }
+            io::Result::Ok(scan)
+        })?;
+        progress.finish();
+        scan.stats = progress.stats();
+        Ok(scan)
+    }
+
+    /// Examine the file system for directories with identical contents.
+    pub fn scan_directories(&self) -> io::Result<DirectoryScan> {
+        let mut progress = Progress::new(self.progress);
+        let mut scan = self.with_sorter(|sorter| dirs::scan(self, sorter, &mut progress))?;
+        progress.finish();
+        scan.stats = progress.stats();
+        Ok(scan)
+    }
+
+    /// Find directories beneath the roots with nothing in them, such as those left behind by
+    /// removing duplicates. Directories are listed deepest first. Unlike a scan for duplicates,
+    /// this always descends into subdirectories, and hidden files count as content.
+    pub fn empty_dirs(&self) -> io::Result<Vec<PathBuf>> {
+        empty::find(self)
+    }
+
+    /// Explain why the sorter prefers file `a` over file `b`.
+    pub fn explain(&self, a: &Path, b: &Path, cache: &Metacache) -> Vec<Reason> {
+        self.with_sorter(|sorter| sorter.explain(a, b, cache))
+    }
+
+    fn with_sorter<T>(&self, f: impl FnOnce(&dyn PathSorter) -> T) -> T {
+        match &self.sorter {
+            Some(sorter) => f(&**sorter),
+            None => f(&get_sorter(&[])),
+        }
+    }
+
+    /// The roots to be examined, grouped by priority, highest first.
+    pub(crate) fn tiers(&self) -> Vec<Vec<PathBuf>> {
+        if !self.compare.is_empty() {
+            vec![self.roots.clone(), self.compare.clone()]
+        } else if self.priority_order {
+            self.roots.iter().map(|root| vec![root.clone()]).collect()
+        } else {
+            vec![self.roots.clone()]
+        }
+    }
+
+    pub(crate) fn accepts(&self, path: &Path, meta: &Meta) -> bool {
+        (self.include_empty || meta.len > 0) && self.filters.iter().all(|filter| filter(path, meta))
+    }
+}
+
+/// The result of a scan.
+pub struct Scan {
+    /// Groups of identical files.
+    pub groups: Vec<Group>,
+
+    /// Metadata for every file appearing in a group.
+    pub cache: Metacache,
+
+    /// The roots examined by the scan, grouped by priority, highest first. When comparing, the
+    /// first tier holds the roots and the second the compare paths. When there is more than one
+    /// tier, these paths are canonical.
+    pub tiers: Vec<Vec<PathBuf>>,
+
+    /// Groups of files which look alike without being identical, if the scan looked for them.
+    pub similar: Vec<Similar>,
+
+    /// Loose files duplicating members of archives, if the scan looked inside them.
+    pub archived: Vec<Archived>,
+
+    /// Figures gathered while scanning.
+    pub stats: Stats,
+}
+
+impl Scan {
+    /// The tier in which a file was found. Where tiers overlap, the file belongs to the first.
+    pub fn tier_of(&self, path: &Path) -> Option<usize> {
+        self.tiers
+            .iter()
+            .position(|roots| roots.iter().any(|root| path.starts_with(root)))
+    }
+
+    /// The root under which a file was found. Where roots overlap, the file belongs to the first.
+    pub fn root_of(&self, path: &Path) -> Option<&Path> {
+        self.tiers
+            .iter()
+            .flatten()
+            .find(|root| path.starts_with(root))
+            .map(AsRef::as_ref)
+    }
+
+    /// Plan the removal of every duplicate file.
+    pub fn plan(&self) -> Plan {
+        Plan::new(self)
+    }
+
+    /// Put the groups of identical files in order. Groups which tie are ordered by the path of
+    /// their first file, so that the order is the same from one scan to the next.
+    pub fn order_groups(&mut self, order: GroupOrder) {
+        let cache = &self.cache;
+        let len = |group: &Group| {
+            group
+                .files
+                .first()
+                .and_then(|path| cache.get(path))
+                .map(|meta| meta.len)
+                .unwrap_or_default()
+        };
+        let wasted = |group: &Group| len(group) * group.duplicates().len() as u64;
+
+        self.groups.sort_by(|a, b| {
+            let ordering = match order {
+                GroupOrder::Size => len(b).cmp(&len(a)),
+                GroupOrder::Count => b.files.len().cmp(&a.files.len()),
+                GroupOrder::Wasted => wasted(b).cmp(&wasted(a)),
+                GroupOrder::Path => std::cmp::Ordering::Equal,
+            };
+            ordering.then_with(|| a.files.cmp(&b.files))
+        });
+    }
+}
+
+/// How to order groups of identical files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupOrder {
+    /// Largest files first.
+    Size,
+
+    /// Groups with the most copies first.
+    Count,
+
+    /// Groups reclaiming the most space first.
+    Wasted,
+
+    /// By the path of the file to be kept.
+    #[default]
+    Path,
+}
+
+impl FromStr for GroupOrder {
+    type Err = ParseGroupOrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "size" => Ok(GroupOrder::Size),
+            "count" => Ok(GroupOrder::Count),
+            "wasted" => Ok(GroupOrder::Wasted),
+            "path" => Ok(GroupOrder::Path),
+            _ => Err(ParseGroupOrderError(s.into())),
+        }
+    }
+}
+
+impl Display for GroupOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupOrder::Size => f.write_str("size"),
+            GroupOrder::Count => f.write_str("count"),
+            GroupOrder::Wasted => f.write_str("wasted"),
+            GroupOrder::Path => f.write_str("path"),
+        }
+    }
+}
+
+/// The error returned when a group order can't be parsed.
+#[derive(Clone, Debug)]
+pub struct ParseGroupOrderError(String);
+
+impl Display for ParseGroupOrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} is not a valid group order\nTry one of size, count, wasted, path",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseGroupOrderError {}
+
+/// A set of files with identical content.
+pub struct Group {
+    /// The imprint shared by every file in the group.
+    pub imprint: Imprint,
+
+    /// The files in this group, in order of preference.
+    pub files: Vec<PathBuf>,
+
+    /// The number of files, from the front of `files`, which are to be kept.
+    pub keep: usize,
+}
+
+impl Group {
+    /// The files to be kept.
+    pub fn keepers(&self) -> &[PathBuf] {
+        &self.files[..self.keep]
+    }
+
+    /// The files which duplicate a keeper.
+    pub fn duplicates(&self) -> &[PathBuf] {
+        &self.files[self.keep..]
+    }
+}
+
+/// A set of files which look alike, without being identical.
+pub struct Similar {
+    /// The files in this group, in order of preference.
+    pub files: Vec<PathBuf>,
+
+    /// The number of files, from the front of `files`, which are to be kept.
+    pub keep: usize,
+
+    /// How far the most different file lies from the first, in bits of difference between their
+    /// hashes.
+    pub distance: u32,
+}
+
+impl Similar {
+    /// The files to be kept.
+    pub fn keepers(&self) -> &[PathBuf] {
+        &self.files[..self.keep]
+    }
+
+    /// The files which resemble a keeper.
+    pub fn duplicates(&self) -> &[PathBuf] {
+        &self.files[self.keep..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{GroupOrder, Scanner};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn groups_are_ordered_and_cut() {
+        let root = TempDir::new("order");
+        for name in ["large-1", "large-2"] {
+            fs::write(root.join(name), "0123456789").unwrap();
+        }
+        for name in ["small-1", "small-2", "small-3"] {
+            fs::write(root.join(name), "01").unwrap();
+        }
+
+        let first = |order, top| {
+            let mut scanner = Scanner::new(&*root).order_groups(order);
+            if let Some(top) = top {
+                scanner = scanner.top(top);
+            }
+            let scan = scanner.scan().unwrap();
+            (scan.groups.len(), scan.groups[0].files.len())
+        };
+
+        let results = [
+            first(GroupOrder::Size, None),
+            first(GroupOrder::Count, None),
+            first(GroupOrder::Wasted, Some(1)),
+        ];
+
+        assert_eq!(results, [(2, 2), (2, 3), (1, 2)]);
+    }
+}
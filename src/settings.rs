@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Format, PathStyle};
+
+/// The name of the per-directory settings file, read from the working directory.
+const LOCAL_SETTINGS: &str = ".yogi.toml";
+
+/// Defaults for command line options, as read from a settings file.
+///
+/// Settings are read first from the user's config directory and then from the working
+/// directory, with later files overriding earlier ones field by field. A named profile, if one
+/// is requested, overrides both. Flags given on the command line override everything.
+///
+/// Relative paths to compare against and to dictionaries are found relative to the settings file
+/// naming them. Relative directories to ignore, prefer or avoid lie beneath the roots being
+/// scanned, and are found relative to each of them.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub compare: Vec<String>,
+    pub ignore: Vec<String>,
+    pub sort: Vec<String>,
+    pub prefer: Vec<String>,
+    pub avoid: Vec<String>,
+    pub dictionary: Vec<String>,
+    pub no_default_dictionary: Option<bool>,
+    pub penalty: Vec<String>,
+    pub no_recurse: Option<bool>,
+    pub priority_order: Option<bool>,
+    pub dedupe_compare: Option<bool>,
+    pub include_empty: Option<bool>,
+    pub quiet: Option<bool>,
+    pub paths: Option<PathStyle>,
+    pub format: Option<Format>,
+    pub order_groups: Option<String>,
+
+    #[serde(skip_serializing)]
+    pub profiles: HashMap<String, Settings>,
+}
+
+impl Settings {
+    pub fn load(profile: Option<&str>) -> io::Result<Self> {
+        let mut settings = Settings::default();
+        let files = user_settings_path()
+            .into_iter()
+            .chain(Some(PathBuf::from(LOCAL_SETTINGS)));
+
+        for path in files {
+            if let Some(file) = Settings::read(&path)? {
+                settings = settings.merge(file);
+            }
+        }
+
+        let Some(name) = profile else {
+            return Ok(settings);
+        };
+
+        let profile = settings.profiles.remove(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no profile named {name:?}"),
+            )
+        })?;
+        Ok(settings.merge(profile))
+    }
+
+    fn read(path: &Path) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut settings: Settings = toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })?;
+
+        if let Some(dir) = path.parent() {
+            settings.resolve(dir);
+        }
+        Ok(Some(settings))
+    }
+
+    /// Paths named in a settings file are found relative to that file.
+    fn resolve(&mut self, dir: &Path) {
+        let paths = [&mut self.compare, &mut self.dictionary];
+        for path in paths.into_iter().flatten() {
+            *path = dir.join(&*path).to_string_lossy().into_owned();
+        }
+
+        for profile in self.profiles.values_mut() {
+            profile.resolve(dir);
+        }
+    }
+
+    /// Find directories to ignore, prefer or avoid beneath each of the roots being scanned.
+    pub fn resolve_beneath(&mut self, roots: &[&str]) {
+        let dirs = [&mut self.ignore, &mut self.prefer, &mut self.avoid];
+        for dirs in dirs {
+            *dirs = dirs
+                .drain(..)
+                .flat_map(|dir| {
+                    if Path::new(&dir).is_absolute() {
+                        vec![dir]
+                    } else {
+                        roots
+                            .iter()
+                            .map(|root| Path::new(root).join(&dir).to_string_lossy().into_owned())
+                            .collect()
+                    }
+                })
+                .collect();
+        }
+    }
+
+    /// Override these settings with any set in `other`.
+    pub fn merge(mut self, other: Settings) -> Self {
+        fn list(base: &mut Vec<String>, other: Vec<String>) {
+            if !other.is_empty() {
+                *base = other;
+            }
+        }
+
+        list(&mut self.compare, other.compare);
+        list(&mut self.ignore, other.ignore);
+        list(&mut self.sort, other.sort);
+        list(&mut self.prefer, other.prefer);
+        list(&mut self.avoid, other.avoid);
+        list(&mut self.dictionary, other.dictionary);
+        list(&mut self.penalty, other.penalty);
+        self.no_recurse = other.no_recurse.or(self.no_recurse);
+        self.priority_order = other.priority_order.or(self.priority_order);
+        self.dedupe_compare = other.dedupe_compare.or(self.dedupe_compare);
+        self.include_empty = other.include_empty.or(self.include_empty);
+        self.no_default_dictionary = other.no_default_dictionary.or(self.no_default_dictionary);
+        self.quiet = other.quiet.or(self.quiet);
+        self.paths = other.paths.or(self.paths);
+        self.format = other.format.or(self.format);
+        self.order_groups = other.order_groups.or(self.order_groups);
+
+        for (name, profile) in other.profiles {
+            let base = self.profiles.remove(&name).unwrap_or_default();
+            self.profiles.insert(name, base.merge(profile));
+        }
+
+        self
+    }
+}
+
+fn user_settings_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(|home| Path::new(&home).join(".config"))
+        })?;
+    Some(config_dir.join("yogi").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Settings;
+
+    #[test]
+    fn later_settings_override_earlier_ones() {
+        let user: Settings = toml::from_str(
+            r#"
+            sort = ["oldest"]
+            ignore = ["tmp"]
+            quiet = true
+
+            [profiles.photos]
+            prefer = ["/photos"]
+            "#,
+        )
+        .unwrap();
+        let local: Settings = toml::from_str(
+            r#"
+            sort = ["newest"]
+            quiet = false
+
+            [profiles.photos]
+            sort = ["alphabetical"]
+            "#,
+        )
+        .unwrap();
+
+        let mut settings = user.merge(local);
+        assert_eq!(settings.sort, ["newest"]);
+        assert_eq!(settings.ignore, ["tmp"]);
+        assert_eq!(settings.quiet, Some(false));
+
+        let profile = settings.profiles.remove("photos").unwrap();
+        let settings = settings.merge(profile);
+        assert_eq!(settings.sort, ["alphabetical"]);
+        assert_eq!(settings.prefer, ["/photos"]);
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_settings_file() {
+        let mut settings: Settings = toml::from_str(
+            r#"
+            ignore = ["tmp"]
+            prefer = ["/photos"]
+            dictionary = ["words.txt"]
+            "#,
+        )
+        .unwrap();
+
+        let dir = Path::new("/home/user/.config/yogi");
+        settings.resolve(dir);
+
+        let resolved = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        assert_eq!(settings.ignore, ["tmp"]);
+        assert_eq!(settings.prefer, ["/photos"]);
+        assert_eq!(settings.dictionary, [resolved("words.txt")]);
+    }
+
+    #[test]
+    fn directories_are_relative_to_each_root() {
+        let mut settings: Settings = toml::from_str(
+            r#"
+            ignore = ["node_modules"]
+            prefer = ["/photos"]
+            avoid = ["inbox"]
+            "#,
+        )
+        .unwrap();
+
+        settings.resolve_beneath(&["a", "b"]);
+
+        let beneath =
+            |root: &str, name: &str| Path::new(root).join(name).to_string_lossy().into_owned();
+        assert_eq!(
+            settings.ignore,
+            [beneath("a", "node_modules"), beneath("b", "node_modules")]
+        );
+        assert_eq!(settings.prefer, ["/photos"]);
+        assert_eq!(
+            settings.avoid,
+            [beneath("a", "inbox"), beneath("b", "inbox")]
+        );
+    }
+}
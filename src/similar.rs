@@ -0,0 +1,663 @@
+use std::{
+    cmp::Reverse,
+    error::Error,
+    f64::consts::PI,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use hashbrown::{HashMap, HashSet};
+use image::{DynamicImage, GrayImage, imageops::FilterType};
+
+use crate::{
+    meta::Meta,
+    multiple::is_conflict,
+    progress::Progress,
+    scan::{Scan, Scanner, Similar},
+    sort::PathSorter,
+    text::{TextHash, normalized_text},
+};
+
+/// File extensions of the image formats we can decode.
+static IMAGE_EXTENSIONS: &[&str] = &["gif", "jpeg", "jpg", "png", "webp"];
+
+/// A way of hashing an image by its appearance, such that images which look alike have hashes
+/// differing in only a few bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageHash {
+    /// Compares each pixel of an 8x8 thumbnail with the mean. Fast, but easily fooled by changes
+    /// in brightness or contrast.
+    Average,
+
+    /// Compares each pixel of an 8x8 thumbnail with its neighbor, tracking gradients rather than
+    /// brightness.
+    Difference,
+
+    /// Compares the low frequencies of a 32x32 thumbnail with their median. The slowest, but the
+    /// most robust against re-encoding and resizing.
+    #[default]
+    Perceptual,
+}
+
+impl ImageHash {
+    /// Hash an image file, or return `None` if it cannot be decoded.
+    pub fn hash(self, path: &Path) -> Option<u64> {
+        self.hash_with_area(path).map(|(hash, _)| hash)
+    }
+
+    /// Hash an image file, also returning its area in pixels.
+    fn hash_with_area(self, path: &Path) -> Option<(u64, u64)> {
+        let image = image::open(path).ok()?;
+        let area = image.width() as u64 * image.height() as u64;
+        Some((self.hash_image(&image), area))
+    }
+
+    fn hash_image(self, image: &DynamicImage) -> u64 {
+        match self {
+            ImageHash::Average => average_hash(image),
+            ImageHash::Difference => difference_hash(image),
+            ImageHash::Perceptual => perceptual_hash(image),
+        }
+    }
+}
+
+impl FromStr for ImageHash {
+    type Err = ParseImageHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "average" | "ahash" => Ok(ImageHash::Average),
+            "difference" | "dhash" => Ok(ImageHash::Difference),
+            "perceptual" | "phash" => Ok(ImageHash::Perceptual),
+            _ => Err(ParseImageHashError(s.into())),
+        }
+    }
+}
+
+/// The error returned when an image hash can't be parsed.
+#[derive(Clone, Debug)]
+pub struct ParseImageHashError(String);
+
+impl Display for ParseImageHashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} is not a valid image hash\nTry one of average, difference, perceptual",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseImageHashError {}
+
+/// Find images which look alike, without being identical, and record them as similar groups.
+///
+/// Images are ranked by resolution ahead of the sorter, so that a thumbnail is never kept over
+/// the original.
+pub fn images(
+    scanner: &Scanner,
+    scan: &mut Scan,
+    sorter: &dyn PathSorter,
+    (hash, max_distance): (ImageHash, u32),
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let hash = |path: &Path| hash.hash_with_area(path);
+    find(
+        scanner,
+        scan,
+        sorter,
+        is_image,
+        hash,
+        max_distance,
+        progress,
+    )
+}
+
+/// Find text files whose contents are nearly the same and record them as similar groups.
+pub fn text(
+    scanner: &Scanner,
+    scan: &mut Scan,
+    sorter: &dyn PathSorter,
+    (hash, max_distance): (TextHash, u32),
+    progress: &mut Progress,
+) -> io::Result<()> {
+    // Normalized hashes are only ever identical or unrelated.
+    let max_distance = match hash {
+        TextHash::Normalized => 0,
+        TextHash::SimHash => max_distance,
+    };
+    let found = scan.similar.len();
+    let text_hash = hash;
+    let hash = |path: &Path| hash.hash(path).map(|hash| (hash, 0));
+    find(
+        scanner,
+        scan,
+        sorter,
+        |_| true,
+        hash,
+        max_distance,
+        progress,
+    )?;
+
+    // A matching hash is no proof that the normalized contents match, so they are compared in
+    // full before anything may be removed.
+    if text_hash == TextHash::Normalized {
+        for group in &mut scan.similar[found..] {
+            let keepers: Vec<_> = group
+                .keepers()
+                .iter()
+                .filter_map(|path| normalized_text(path))
+                .collect();
+            let mut idx = 0;
+            group.files.retain(|path| {
+                idx += 1;
+                idx <= group.keep
+                    || normalized_text(path).is_some_and(|text| keepers.contains(&text))
+            });
+        }
+        scan.similar.retain(|group| !group.duplicates().is_empty());
+    }
+
+    Ok(())
+}
+
+/// Group files whose hashes lie within `max_distance` bits of one another.
+///
+/// Only files passing the filter are hashed. The hash function returns `None` for files it has
+/// nothing to say about, and otherwise a hash along with a measure of quality. Files already found
+/// to be exact duplicates are left out; only the file kept from each such group is compared against
+/// other files. Within a similar group, files are ranked by tier, as they would be in an exact
+/// group, then by quality, highest first, and only then by the sorter. Files further than
+/// `max_distance` from every file kept are left out of the group, however closely they are chained
+/// to it.
+fn find(
+    scanner: &Scanner,
+    scan: &mut Scan,
+    sorter: &dyn PathSorter,
+    filter: impl Fn(&Path) -> bool,
+    hash: impl Fn(&Path) -> Option<(u64, u64)>,
+    max_distance: u32,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let duplicates: HashSet<&Path> = scan
+        .groups
+        .iter()
+        .flat_map(|group| group.duplicates())
+        .map(AsRef::as_ref)
+        .collect();
+
+    let candidates: Vec<_> = walk(scanner)?
+        .into_iter()
+        .filter(|(_, path, _)| filter(path) && !duplicates.contains(path.as_path()))
+        .collect();
+
+    let bytes = candidates.iter().map(|(_, _, meta)| meta.len).sum();
+    progress.candidates(candidates.len(), bytes);
+
+    let mut hashed = Vec::new();
+    for (tier, path, meta) in candidates {
+        let file_hash = hash(&path);
+        progress.hashed(meta.len);
+        if let Some((file_hash, quality)) = file_hash {
+            hashed.push((tier, path, meta, file_hash, quality));
+        }
+    }
+
+    let hashes: Vec<_> = hashed.iter().map(|&(.., hash, _)| hash).collect();
+    let tier_count = scanner.tiers().len();
+    let keep_base = !scanner.compare.is_empty();
+
+    for cluster in cluster(&hashes, max_distance) {
+        let mut tiers = vec![Vec::new(); tier_count];
+        for &idx in &cluster {
+            tiers[hashed[idx].0].push(idx);
+        }
+
+        let base = tiers[0].len();
+        let rest = cluster.len() - base;
+        if !is_conflict(keep_base, scanner.dedupe_compare, base, rest) {
+            continue;
+        }
+
+        for &idx in &cluster {
+            let (_, path, meta, ..) = &hashed[idx];
+            scan.cache.insert(path.clone(), meta.clone());
+        }
+
+        // The hash and quality of each file.
+        let file_of: HashMap<&Path, (u64, u64)> = cluster
+            .iter()
+            .map(|&idx| (hashed[idx].1.as_path(), (hashed[idx].3, hashed[idx].4)))
+            .collect();
+
+        let keep = if keep_base { base.max(1) } else { 1 };
+        let files: Vec<_> = tiers
+            .into_iter()
+            .flat_map(|indices| {
+                let mut files: Vec<_> = indices.iter().map(|&idx| hashed[idx].1.clone()).collect();
+                sorter.sort(&mut files, &scan.cache);
+
+                // The sort by quality is stable, leaving the sorter to break ties.
+                files.sort_by_key(|path| Reverse(file_of[path.as_path()].1));
+                files
+            })
+            .collect();
+
+        // Clusters are joined by chains of close hashes, so the far end of a chain may look nothing
+        // like the file kept. Only files close to a keeper are similar to it.
+        let hash_of = |path: &PathBuf| file_of[path.as_path()].0;
+        let (keepers, rest) = files.split_at(keep);
+        let close = |path: &&PathBuf| {
+            keepers
+                .iter()
+                .any(|keeper| (hash_of(keeper) ^ hash_of(path)).count_ones() <= max_distance)
+        };
+        let files: Vec<_> = keepers
+            .iter()
+            .chain(rest.iter().filter(close))
+            .cloned()
+            .collect();
+        if files.len() == keep {
+            continue;
+        }
+
+        let first = hash_of(&files[0]);
+        let distance = files
+            .iter()
+            .map(|path| (first ^ hash_of(path)).count_ones())
+            .max()
+            .unwrap_or_default();
+
+        scan.similar.push(Similar {
+            files,
+            keep,
+            distance,
+        });
+    }
+
+    Ok(())
+}
+
+/// Walk the scanner's tiers, returning each file with the tier in which it was found. As in the
+/// scan proper, paths are canonical when there is more than one tier, and a file reachable from
+/// more than one root belongs to the first of them.
+pub(crate) fn walk(scanner: &Scanner) -> io::Result<Vec<(usize, PathBuf, Meta)>> {
+    let tiers = scanner.tiers();
+    let canonical = tiers.len() > 1;
+    let overlapping = tiers.iter().map(Vec::len).sum::<usize>() > 1;
+
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+
+    for (tier, roots) in tiers.iter().enumerate() {
+        let entries = roots
+            .iter()
+            .flat_map(|root| crate::list_entries(root, scanner.recurse, &scanner.ignore));
+
+        for entry in entries {
+            let path = if canonical {
+                let Some(path) = crate::canonical(entry.path()) else {
+                    continue;
+                };
+                path
+            } else {
+                entry.path().to_owned()
+            };
+
+            if overlapping && !crate::canonical(&path).is_some_and(|path| seen.insert(path)) {
+                continue;
+            }
+
+            let Some(meta) = crate::metadata(&path) else {
+                continue;
+            };
+            if scanner.accepts(&path, &meta) {
+                files.push((tier, path, meta));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Whether a file appears, by its extension, to be an image which can be decoded.
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Group hashes lying within the given distance of one another, returning the indices of each
+/// group of two or more. Grouping is transitive: two hashes may be further apart than the
+/// distance so long as a chain of close hashes connects them, so a group must be trimmed to the
+/// files close to the one kept before anything is removed from it.
+fn cluster(hashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut tree = BkTree::default();
+    let mut sets = DisjointSet::new(hashes.len());
+    let mut neighbors = Vec::new();
+
+    for (idx, &hash) in hashes.iter().enumerate() {
+        neighbors.clear();
+        tree.find(hash, max_distance, &mut neighbors);
+        for &neighbor in &neighbors {
+            sets.union(idx, neighbor);
+        }
+        tree.insert(hash, idx);
+    }
+
+    let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+    for idx in 0..hashes.len() {
+        clusters.entry(sets.find(idx)).or_default().push(idx);
+    }
+
+    let mut clusters: Vec<_> = clusters
+        .into_values()
+        .filter(|cluster| cluster.len() > 1)
+        .collect();
+    clusters.sort();
+    clusters
+}
+
+/// A metric tree over Hamming distance, so that finding close hashes doesn't require comparing
+/// each hash with every other.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    item: usize,
+    children: Vec<(u32, usize)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, item: usize) {
+        let new = self.nodes.len();
+        self.nodes.push(BkNode {
+            hash,
+            item,
+            children: Vec::new(),
+        });
+
+        if new == 0 {
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let distance = (self.nodes[current].hash ^ hash).count_ones();
+            match self.nodes[current]
+                .children
+                .iter()
+                .find(|&&(d, _)| d == distance)
+            {
+                Some(&(_, child)) => current = child,
+                None => {
+                    self.nodes[current].children.push((distance, new));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn find(&self, hash: u64, max_distance: u32, items: &mut Vec<usize>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(current) = stack.pop() {
+            let node = &self.nodes[current];
+            let distance = (node.hash ^ hash).count_ones();
+            if distance <= max_distance {
+                items.push(node.item);
+            }
+
+            // By the triangle inequality, only children at about the same distance from this
+            // node as the hash can be close to the hash.
+            let range = distance.saturating_sub(max_distance)..=distance + max_distance;
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| range.contains(d))
+                    .map(|&(_, child)| child),
+            );
+        }
+    }
+}
+
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut idx: usize) -> usize {
+        while self.parents[idx] != idx {
+            self.parents[idx] = self.parents[self.parents[idx]];
+            idx = self.parents[idx];
+        }
+        idx
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[b.max(a)] = a.min(b);
+        }
+    }
+}
+
+fn thumbnail(image: &DynamicImage, width: u32, height: u32) -> GrayImage {
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8()
+}
+
+fn average_hash(image: &DynamicImage) -> u64 {
+    let pixels = thumbnail(image, 8, 8);
+    let mean = pixels.pixels().map(|p| p[0] as u32).sum::<u32>() / 64;
+    to_bits(pixels.pixels().map(|p| p[0] as u32 > mean))
+}
+
+fn difference_hash(image: &DynamicImage) -> u64 {
+    let pixels = thumbnail(image, 9, 8);
+    to_bits(
+        (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .map(|(x, y)| pixels.get_pixel(x, y)[0] > pixels.get_pixel(x + 1, y)[0]),
+    )
+}
+
+fn perceptual_hash(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+
+    let pixels = thumbnail(image, SIZE as u32, SIZE as u32);
+    let values: Vec<f64> = pixels.pixels().map(|p| p[0] as f64).collect();
+
+    // Only the lowest frequencies of the discrete cosine transform are wanted, so rather than
+    // transform the whole image, we compute just those.
+    let basis: Vec<[f64; SIZE]> = (0..LOW)
+        .map(|k| {
+            let mut row = [0.0; SIZE];
+            for (n, value) in row.iter_mut().enumerate() {
+                *value = (PI * (2 * n + 1) as f64 * k as f64 / (2 * SIZE) as f64).cos();
+            }
+            row
+        })
+        .collect();
+
+    let rows: Vec<[f64; LOW]> = values
+        .chunks(SIZE)
+        .map(|row| {
+            let mut low = [0.0; LOW];
+            for (k, value) in low.iter_mut().enumerate() {
+                *value = row.iter().zip(&basis[k]).map(|(x, c)| x * c).sum();
+            }
+            low
+        })
+        .collect();
+
+    let coefficients: Vec<f64> = (0..LOW)
+        .flat_map(|v| (0..LOW).map(move |u| (u, v)))
+        .map(|(u, v)| rows.iter().zip(&basis[v]).map(|(row, c)| row[u] * c).sum())
+        .collect();
+
+    // The first coefficient is the average brightness, which would skew the median.
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    to_bits(coefficients.iter().map(|&c| c > median))
+}
+
+fn to_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |hash, bit| hash << 1 | bit as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use image::{DynamicImage, ImageBuffer, Luma};
+
+    use super::{ImageHash, cluster, find};
+    use crate::{
+        plan::Plan, progress::Progress, scan::Scanner, sort::get_sorter, testing::TempDir,
+    };
+
+    /// Smooth noise: a coarse grid of pseudo-random values, interpolated across the image.
+    fn pattern(width: u32, height: u32, invert: bool) -> DynamicImage {
+        const GRID: usize = 12;
+
+        let mut state = 0x2545_f491_u32;
+        let grid: Vec<f64> = (0..GRID * GRID)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % 256) as f64
+            })
+            .collect();
+
+        DynamicImage::ImageLuma8(ImageBuffer::from_fn(width, height, |x, y| {
+            let x = x as f64 / width as f64 * (GRID - 1) as f64;
+            let y = y as f64 / height as f64 * (GRID - 1) as f64;
+            let (i, j) = (x as usize, y as usize);
+            let (fx, fy) = (x.fract(), y.fract());
+            let at = |i: usize, j: usize| grid[j * GRID + i];
+            let top = at(i, j) * (1.0 - fx) + at(i + 1, j) * fx;
+            let bottom = at(i, j + 1) * (1.0 - fx) + at(i + 1, j + 1) * fx;
+            let value = (top * (1.0 - fy) + bottom * fy) as u8;
+            Luma([if invert { 255 - value } else { value }])
+        }))
+    }
+
+    #[test]
+    fn resized_images_hash_alike() {
+        let original = pattern(640, 480, false);
+        let resized = pattern(200, 150, false);
+        let different = pattern(640, 480, true);
+
+        for hash in [
+            ImageHash::Average,
+            ImageHash::Difference,
+            ImageHash::Perceptual,
+        ] {
+            let a = hash.hash_image(&original);
+            let b = hash.hash_image(&resized);
+            let c = hash.hash_image(&different);
+            assert!((a ^ b).count_ones() <= 6, "{hash:?}");
+            assert!((a ^ c).count_ones() > 16, "{hash:?}");
+        }
+    }
+
+    #[test]
+    fn close_hashes_cluster_together() {
+        let hashes = [0b0000, u64::MAX, 0b0011, 0b0111, u64::MAX - 1, 0x0f0f_0000];
+        assert_eq!(cluster(&hashes, 1), [vec![1, 4], vec![2, 3]]);
+
+        // 0b0000 is two bits from 0b0011, and so joins it along with 0b0111.
+        assert_eq!(cluster(&hashes, 2), [vec![0, 2, 3], vec![1, 4]]);
+    }
+
+    #[test]
+    fn files_far_from_the_keeper_are_not_similar() {
+        let root = TempDir::new("similar");
+        for name in ["a", "b", "c"] {
+            fs::write(root.join(name), name).unwrap();
+        }
+
+        // b is close to both a and c, which are far from one another.
+        let hash = |path: &Path| match path.file_name()?.to_str()? {
+            "a" => Some((0b0000, 1)),
+            "b" => Some((0b0011, 0)),
+            "c" => Some((0b1111, 0)),
+            _ => None,
+        };
+        let scanner = Scanner::new(&*root);
+        let mut scan = scanner.scan().unwrap();
+        find(
+            &scanner,
+            &mut scan,
+            &get_sorter(&[]),
+            |_| true,
+            hash,
+            2,
+            &mut Progress::new(false),
+        )
+        .unwrap();
+
+        assert_eq!(scan.similar.len(), 1);
+        assert_eq!(scan.similar[0].files, [root.join("a"), root.join("b")]);
+    }
+
+    #[test]
+    fn exact_keepers_resembling_a_better_file_are_removed_with_their_group() {
+        let root = TempDir::new("similar-exact");
+        for (name, contents) in [("a-1", "small"), ("a-2", "small"), ("b", "large")] {
+            fs::write(root.join(name), contents).unwrap();
+        }
+
+        // b is a better copy of a-1 and a-2, which are identical.
+        let hash = |path: &Path| match path.file_name()?.to_str()? {
+            "a-1" | "a-2" => Some((0b0000, 1)),
+            "b" => Some((0b0001, 2)),
+            _ => None,
+        };
+        let scanner = Scanner::new(&*root);
+        let mut scan = scanner.scan().unwrap();
+        find(
+            &scanner,
+            &mut scan,
+            &get_sorter(&[]),
+            |_| true,
+            hash,
+            2,
+            &mut Progress::new(false),
+        )
+        .unwrap();
+
+        let removed = Plan::new(&scan).include_similar(&scan).execute().unwrap();
+        let left: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+
+        assert_eq!(removed.files, 2);
+        assert_eq!(left, ["b"]);
+    }
+}
@@ -1,96 +1,95 @@
-use std::{cmp::Reverse, io, path::Path};
+use std::{io, path::Path};
 
 use bumpalo::Bump;
-use fmtsize::{Conventional, FmtSize};
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use imprint::Imprint;
 
-use crate::{config::SortOrder, meta::Meta, rank::PathRanker, Metacache};
-
-trait PathSorter {
-    fn sort(&self, paths: &mut [&Path]);
-}
-
-impl PathSorter for PathRanker {
-    fn sort(&self, paths: &mut [&Path]) {
-        paths.sort_by_cached_key(|&p| Reverse(self.rank(p)));
-    }
-}
-
-struct MetaSorter<'a> {
-    by_newest: bool,
-    cache: &'a Metacache<'a>,
-}
-
-impl PathSorter for MetaSorter<'_> {
-    fn sort(&self, paths: &mut [&Path]) {
-        if self.by_newest {
-            paths.sort_by_key(|&path| Reverse(self.cache.get(path).unwrap().created));
-        } else {
-            paths.sort_by_key(|&path| self.cache.get(path).unwrap().created);
-        }
-    }
-}
-
-fn get_sorter<'a>(sort: SortOrder, cache: &'a Metacache<'a>) -> Box<dyn PathSorter + 'a> {
-    match sort {
-        SortOrder::Descriptive => Box::new(PathRanker::new()),
-        SortOrder::Newest => Box::new(MetaSorter {
-            by_newest: true,
-            cache,
-        }),
-        SortOrder::Oldest => Box::new(MetaSorter {
-            by_newest: false,
-            cache,
-        }),
-    }
-}
-
-pub fn process(
-    path: &str,
-    sort: SortOrder,
-    force: bool,
-    recurse: bool,
-    ignored: &[&Path],
-) -> io::Result<()> {
+use crate::{
+    meta::{Meta, Metacache},
+    progress::Progress,
+    scan::{Group, Scan, Scanner},
+    sort::PathSorter,
+};
+
+pub fn scan(
+    scanner: &Scanner,
+    sorter: &dyn PathSorter,
+    progress: &mut Progress,
+) -> io::Result<Scan> {
     // We need the arena to be allocated first so that it can be dropped last.
     let paths = Bump::new();
 
-    let mut metacache = Metacache::new();
+    let mut metacache = HashMap::new();
+
+    let conflicts_by_len = build_conflicts_by_length(scanner, &paths, &mut metacache, progress)?;
+    let conflicts_by_imprint = build_conflicts_by_imprint(conflicts_by_len, &metacache, progress)?;
 
-    let conflicts_by_len =
-        build_conflicts_by_length(path, &paths, &mut metacache, recurse, ignored)?;
-    let mut conflicts_by_imprint = build_conflicts_by_imprint(conflicts_by_len)?;
+    // Only the files which turned out to have duplicates are worth keeping track of.
+    let mut cache = Metacache::new();
+    let mut groups: Vec<_> = conflicts_by_imprint
+        .into_iter()
+        .map(|(imprint, group)| Group {
+            imprint,
+            files: group
+                .into_iter()
+                .map(|path| {
+                    cache.insert(path.to_owned(), metacache[path].clone());
+                    path.to_owned()
+                })
+                .collect(),
+            keep: 1,
+        })
+        .collect();
 
     // Sorting before deconfliction or formatting ensures that deconfliction behavior is
     // previewed appropriately.
-    let sorter = get_sorter(sort, &metacache);
-    conflicts_by_imprint
-        .iter_mut()
-        .for_each(|x| sorter.sort(&mut x.1));
-
-    if force {
-        let (count, size) = super::deconflict(conflicts_by_imprint, &metacache)?;
-        println!("Removed {} files ({})", count, size.fmt_size(Conventional));
-    } else {
-        super::pretty_print_conflicts(conflicts_by_imprint, &metacache)?;
+    for group in &mut groups {
+        sorter.sort(&mut group.files, &cache);
     }
 
-    Ok(())
+    Ok(Scan {
+        groups,
+        cache,
+        tiers: vec![scanner.roots.clone()],
+        similar: Vec::new(),
+        archived: Vec::new(),
+        stats: Default::default(),
+    })
 }
 
 fn build_conflicts_by_length<'a>(
-    path: &str,
+    scanner: &Scanner,
     path_src: &'a Bump,
-    metacache: &mut Metacache<'a>,
-    recurse: bool,
-    ignored: &[&Path],
-) -> io::Result<impl Iterator<Item = &'a Path>> {
+    metacache: &mut HashMap<&'a Path, Meta>,
+    progress: &mut Progress,
+) -> io::Result<Vec<&'a Path>> {
     let mut candidates = HashMap::new();
 
-    for entry in super::list_entries(path, recurse, ignored) {
+    // Roots may overlap, in which case we could find the same file twice and conclude that it's
+    // a duplicate of itself.
+    let mut seen = HashSet::new();
+    let overlapping = scanner.roots.len() > 1;
+
+    let entries = scanner
+        .roots
+        .iter()
+        .flat_map(|root| crate::list_entries(root, scanner.recurse, &scanner.ignore));
+
+    for entry in entries {
+        progress.walked();
+        if overlapping && !crate::canonical(entry.path()).is_some_and(|path| seen.insert(path)) {
+            continue;
+        }
+
+        let Some(meta) = crate::metadata(entry.path()) else {
+            continue;
+        };
         let path = &**path_src.alloc(entry.path().to_owned());
-        let meta: Meta = path.metadata()?.into();
+        if !scanner.accepts(path, &meta) {
+            continue;
+        }
+
+        progress.examined(meta.len);
         candidates
             .entry(meta.len)
             .or_insert_with(Vec::new)
@@ -98,19 +97,32 @@ fn build_conflicts_by_length<'a>(
         metacache.insert(path, meta);
     }
 
-    Ok(candidates
+    let candidates: Vec<_> = candidates
         .into_iter()
         .filter(|x| x.1.len() > 1)
-        .flat_map(|x| x.1.into_iter()))
+        .flat_map(|x| x.1.into_iter())
+        .collect();
+
+    let bytes = candidates
+        .iter()
+        .filter_map(|&path| metacache.get(path))
+        .map(|meta| meta.len)
+        .sum();
+    progress.candidates(candidates.len(), bytes);
+
+    Ok(candidates)
 }
 
 fn build_conflicts_by_imprint<'a>(
     paths: impl IntoIterator<Item = &'a Path>,
+    metacache: &HashMap<&'a Path, Meta>,
+    progress: &mut Progress,
 ) -> io::Result<Vec<(Imprint, Vec<&'a Path>)>> {
     let mut candidates = HashMap::new();
 
     for path in paths {
         let imprint = Imprint::new(path)?;
+        progress.hashed(metacache.get(path).map(|x| x.len).unwrap_or_default());
         candidates
             .entry(imprint)
             .or_insert_with(Vec::new)
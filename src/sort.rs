@@ -0,0 +1,705 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use crate::{
+    meta::{Meta, Metacache},
+    rank::PathRanker,
+};
+
+/// A strategy for choosing which of a set of identical files to keep.
+pub trait PathSorter {
+    /// Sort paths in order of preference. The first path is the one to be kept.
+    fn sort(&self, paths: &mut [PathBuf], cache: &Metacache);
+
+    /// Explain why `a` is sorted before `b`, if possible.
+    fn explain(&self, _a: &Path, _b: &Path, _cache: &Metacache) -> Vec<Reason> {
+        Vec::new()
+    }
+}
+
+/// A single criterion for preferring one file over another.
+///
+/// Rules are composed into a [`Strategy`], where each rule only gets a say when every rule
+/// before it considers two files equal. Any rule can be used as a [`PathSorter`] by itself, in
+/// which case files the rule considers equal are ordered by path.
+pub trait Rule {
+    /// Compare two files, where `Ordering::Less` means `a` is preferred.
+    fn compare(&self, a: &Path, b: &Path, cache: &Metacache) -> Ordering;
+
+    /// Prepare to compare many files at once, returning a function which compares two of them by
+    /// their indices in `paths`. Rules which do expensive work for each file, such as reading
+    /// or ranking it, should do that work here, once per file, rather than on every comparison.
+    fn comparator<'a>(
+        &'a self,
+        paths: &'a [PathBuf],
+        cache: &'a Metacache,
+    ) -> Box<dyn Fn(usize, usize) -> Ordering + 'a> {
+        Box::new(move |a, b| self.compare(&paths[a], &paths[b], cache))
+    }
+
+    /// The name of this rule, as used in explanations.
+    fn name(&self) -> String {
+        String::from("custom")
+    }
+
+    /// What this rule sees when it looks at a file, e.g. the timestamp it compares.
+    fn describe(&self, _path: &Path, _cache: &Metacache) -> String {
+        String::new()
+    }
+
+    /// Explain how this rule compares two files. Rules made up of other rules explain each rule
+    /// in turn, up to the one which decides between the files.
+    fn explain(&self, a: &Path, b: &Path, cache: &Metacache) -> Vec<Reason> {
+        vec![Reason {
+            rule: self.name(),
+            ordering: self.compare(a, b, cache),
+            a: self.describe(a, cache),
+            b: self.describe(b, cache),
+        }]
+    }
+}
+
+impl<R: Rule + ?Sized> PathSorter for R {
+    fn sort(&self, paths: &mut [PathBuf], cache: &Metacache) {
+        let mut order: Vec<_> = (0..paths.len()).collect();
+        {
+            let compare = self.comparator(paths, cache);
+            order.sort_by(|&a, &b| compare(a, b).then_with(|| paths[a].cmp(&paths[b])));
+        }
+
+        let mut sorted: Vec<_> = order
+            .into_iter()
+            .map(|idx| std::mem::take(&mut paths[idx]))
+            .collect();
+        paths.swap_with_slice(&mut sorted);
+    }
+
+    fn explain(&self, a: &Path, b: &Path, cache: &Metacache) -> Vec<Reason> {
+        Rule::explain(self, a, b, cache)
+    }
+}
+
+/// How a single rule compared two files.
+#[derive(Clone, Debug)]
+pub struct Reason {
+    /// The name of the rule.
+    pub rule: String,
+
+    /// The rule's preference, where `Ordering::Less` means the first file is preferred.
+    pub ordering: Ordering,
+
+    /// What the rule saw in the first file. May be empty.
+    pub a: String,
+
+    /// What the rule saw in the second file. May be empty.
+    pub b: String,
+}
+
+/// An ordered chain of rules, each breaking ties left by the last.
+#[derive(Default)]
+pub struct Strategy {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Strategy {
+    /// A strategy with no rules, which considers every file equal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule to be consulted when all prior rules consider two files equal.
+    pub fn then(self, rule: impl Rule + 'static) -> Self {
+        self.then_boxed(Box::new(rule))
+    }
+
+    /// As [`Strategy::then`], for a rule already boxed, such as one from [`SortOrder::rule`].
+    pub fn then_boxed(mut self, rule: Box<dyn Rule>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+}
+
+impl Rule for Strategy {
+    fn compare(&self, a: &Path, b: &Path, cache: &Metacache) -> Ordering {
+        self.rules
+            .iter()
+            .map(|rule| rule.compare(a, b, cache))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    fn comparator<'a>(
+        &'a self,
+        paths: &'a [PathBuf],
+        cache: &'a Metacache,
+    ) -> Box<dyn Fn(usize, usize) -> Ordering + 'a> {
+        let comparators: Vec<_> = self
+            .rules
+            .iter()
+            .map(|rule| rule.comparator(paths, cache))
+            .collect();
+
+        Box::new(move |a, b| {
+            comparators
+                .iter()
+                .map(|compare| compare(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+
+    fn name(&self) -> String {
+        let names: Vec<_> = self.rules.iter().map(|rule| rule.name()).collect();
+        names.join(",")
+    }
+
+    fn explain(&self, a: &Path, b: &Path, cache: &Metacache) -> Vec<Reason> {
+        let mut reasons = Vec::new();
+        for rule in &self.rules {
+            reasons.extend(rule.explain(a, b, cache));
+            if reasons.last().is_some_and(|reason| reason.ordering.is_ne()) {
+                break;
+            }
+        }
+        reasons
+    }
+}
+
+impl Rule for PathRanker {
+    fn compare(&self, a: &Path, b: &Path, _cache: &Metacache) -> Ordering {
+        Reverse(self.rank(a)).cmp(&Reverse(self.rank(b)))
+    }
+
+    fn comparator<'a>(
+        &'a self,
+        paths: &'a [PathBuf],
+        _cache: &'a Metacache,
+    ) -> Box<dyn Fn(usize, usize) -> Ordering + 'a> {
+        let ranks: Vec<_> = paths.iter().map(|path| Reverse(self.rank(path))).collect();
+        Box::new(move |a, b| ranks[a].cmp(&ranks[b]))
+    }
+
+    fn name(&self) -> String {
+        String::from("descriptive")
+    }
+
+    fn describe(&self, path: &Path, _cache: &Metacache) -> String {
+        self.rank(path).to_string()
+    }
+}
+
+/// Which of a file's timestamps to sort by.
+#[derive(Clone, Copy, Debug)]
+pub enum Timestamp {
+    /// When the file was created.
+    Created,
+
+    /// When the file was last modified.
+    Modified,
+
+    /// When the file was last accessed.
+    Accessed,
+}
+
+impl Timestamp {
+    fn of(self, meta: &Meta) -> Option<SystemTime> {
+        match self {
+            Timestamp::Created => meta.created,
+            Timestamp::Modified => meta.modified,
+            Timestamp::Accessed => meta.accessed,
+        }
+    }
+}
+
+/// Prefers files by one of their timestamps.
+///
+/// Not every file system records every timestamp. A file missing the timestamp always loses to a
+/// file which has one, whichever direction we're sorting in; between two files missing it, this
+/// rule has no preference and the decision falls to the next rule.
+#[derive(Clone, Copy, Debug)]
+pub struct MetaSorter {
+    timestamp: Timestamp,
+    by_newest: bool,
+}
+
+impl MetaSorter {
+    /// Prefer the file with the latest timestamp.
+    pub fn newest(timestamp: Timestamp) -> Self {
+        Self {
+            timestamp,
+            by_newest: true,
+        }
+    }
+
+    /// Prefer the file with the earliest timestamp.
+    pub fn oldest(timestamp: Timestamp) -> Self {
+        Self {
+            timestamp,
+            by_newest: false,
+        }
+    }
+}
+
+impl Rule for MetaSorter {
+    fn compare(&self, a: &Path, b: &Path, cache: &Metacache) -> Ordering {
+        let a = cache.get(a).and_then(|meta| self.timestamp.of(meta));
+        let b = cache.get(b).and_then(|meta| self.timestamp.of(meta));
+        match (a, b) {
+            (Some(a), Some(b)) if self.by_newest => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    fn name(&self) -> String {
+        let timestamp = match self.timestamp {
+            Timestamp::Created => "",
+            Timestamp::Modified => "modified-",
+            Timestamp::Accessed => "accessed-",
+        };
+        let direction = if self.by_newest { "newest" } else { "oldest" };
+        format!("{timestamp}{direction}")
+    }
+
+    fn describe(&self, path: &Path, cache: &Metacache) -> String {
+        match cache.get(path).and_then(|meta| self.timestamp.of(meta)) {
+            Some(time) => humantime::format_rfc3339(time).to_string(),
+            None => String::from("no timestamp"),
+        }
+    }
+}
+
+/// Prefers files by how deeply they are nested.
+///
+/// When preferring the shortest path, paths of equal depth are further compared by length.
+#[derive(Clone, Copy, Debug)]
+pub struct Depth {
+    by_deepest: bool,
+}
+
+impl Depth {
+    /// Prefer the file nested least deeply.
+    pub fn shortest() -> Self {
+        Self { by_deepest: false }
+    }
+
+    /// Prefer the file nested most deeply.
+    pub fn deepest() -> Self {
+        Self { by_deepest: true }
+    }
+}
+
+impl Rule for Depth {
+    fn compare(&self, a: &Path, b: &Path, _cache: &Metacache) -> Ordering {
+        let depth = |path: &Path| path.components().count();
+        if self.by_deepest {
+            depth(b).cmp(&depth(a))
+        } else {
+            depth(a)
+                .cmp(&depth(b))
+                .then_with(|| a.as_os_str().len().cmp(&b.as_os_str().len()))
+        }
+    }
+
+    fn name(&self) -> String {
+        let name = if self.by_deepest {
+            "deepest-path"
+        } else {
+            "shortest-path"
+        };
+        String::from(name)
+    }
+
+    fn describe(&self, path: &Path, _cache: &Metacache) -> String {
+        let depth = path.components().count();
+        if self.by_deepest {
+            format!("depth {depth}")
+        } else {
+            format!("depth {depth}, length {}", path.as_os_str().len())
+        }
+    }
+}
+
+/// Prefers files in alphabetical order by path.
+#[derive(Clone, Copy, Debug)]
+pub struct Alphabetical;
+
+impl Rule for Alphabetical {
+    fn compare(&self, a: &Path, b: &Path, _cache: &Metacache) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn name(&self) -> String {
+        String::from("alphabetical")
+    }
+}
+
+/// Prefers files found beneath a given directory.
+#[derive(Clone, Debug)]
+pub struct Prefer(Directory);
+
+impl Prefer {
+    /// Prefer files beneath `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(Directory::new(dir.into()))
+    }
+}
+
+impl Rule for Prefer {
+    fn compare(&self, a: &Path, b: &Path, _cache: &Metacache) -> Ordering {
+        // true sorts after false, so reverse to put contained paths first.
+        self.0.contains(b).cmp(&self.0.contains(a))
+    }
+
+    fn comparator<'a>(
+        &'a self,
+        paths: &'a [PathBuf],
+        _cache: &'a Metacache,
+    ) -> Box<dyn Fn(usize, usize) -> Ordering + 'a> {
+        let contained: Vec<_> = paths.iter().map(|path| self.0.contains(path)).collect();
+        Box::new(move |a, b| contained[b].cmp(&contained[a]))
+    }
+
+    fn name(&self) -> String {
+        format!("prefer:{}", self.0.dir.display())
+    }
+
+    fn describe(&self, path: &Path, _cache: &Metacache) -> String {
+        self.0.describe(path)
+    }
+}
+
+/// Prefers files found anywhere other than beneath a given directory.
+#[derive(Clone, Debug)]
+pub struct Avoid(Directory);
+
+impl Avoid {
+    /// Prefer files anywhere but beneath `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(Directory::new(dir.into()))
+    }
+}
+
+impl Rule for Avoid {
+    fn compare(&self, a: &Path, b: &Path, _cache: &Metacache) -> Ordering {
+        self.0.contains(a).cmp(&self.0.contains(b))
+    }
+
+    fn comparator<'a>(
+        &'a self,
+        paths: &'a [PathBuf],
+        _cache: &'a Metacache,
+    ) -> Box<dyn Fn(usize, usize) -> Ordering + 'a> {
+        let contained: Vec<_> = paths.iter().map(|path| self.0.contains(path)).collect();
+        Box::new(move |a, b| contained[a].cmp(&contained[b]))
+    }
+
+    fn name(&self) -> String {
+        format!("avoid:{}", self.0.dir.display())
+    }
+
+    fn describe(&self, path: &Path, _cache: &Metacache) -> String {
+        self.0.describe(path)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Directory {
+    dir: PathBuf,
+    canonical: Option<PathBuf>,
+}
+
+impl Directory {
+    fn new(dir: PathBuf) -> Self {
+        let canonical = dir.canonicalize().ok();
+        Self { dir, canonical }
+    }
+
+    /// Scanned paths may or may not be canonical, depending on the kind of scan, so we try both.
+    fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.dir)
+            || self.canonical.as_ref().is_some_and(|dir| {
+                path.starts_with(dir) || path.canonicalize().is_ok_and(|path| path.starts_with(dir))
+            })
+    }
+
+    fn describe(&self, path: &Path) -> String {
+        let describe = if self.contains(path) {
+            "inside"
+        } else {
+            "outside"
+        };
+        String::from(describe)
+    }
+}
+
+/// The built-in rules for choosing which file to keep.
+#[derive(Clone, Debug, Default)]
+pub enum SortOrder {
+    /// The most descriptive path, as ranked by [`PathRanker`].
+    #[default]
+    Descriptive,
+
+    /// The most recently created file.
+    Newest,
+
+    /// The earliest created file.
+    Oldest,
+
+    /// The most recently modified file.
+    ModifiedNewest,
+
+    /// The earliest modified file.
+    ModifiedOldest,
+
+    /// The most recently accessed file.
+    AccessedNewest,
+
+    /// The earliest accessed file.
+    AccessedOldest,
+
+    /// The file nested least deeply.
+    ShortestPath,
+
+    /// The file nested most deeply.
+    DeepestPath,
+
+    /// The first file by path.
+    Alphabetical,
+
+    /// Files beneath the given directory.
+    Prefer(PathBuf),
+
+    /// Files anywhere but beneath the given directory.
+    Avoid(PathBuf),
+}
+
+impl SortOrder {
+    /// The rule for this sort order. The descriptive order ranks paths using the given ranker.
+    pub fn rule(&self, ranker: &PathRanker) -> Box<dyn Rule> {
+        match self {
+            SortOrder::Descriptive => Box::new(ranker.clone()),
+            SortOrder::Newest => Box::new(MetaSorter::newest(Timestamp::Created)),
+            SortOrder::Oldest => Box::new(MetaSorter::oldest(Timestamp::Created)),
+            SortOrder::ModifiedNewest => Box::new(MetaSorter::newest(Timestamp::Modified)),
+            SortOrder::ModifiedOldest => Box::new(MetaSorter::oldest(Timestamp::Modified)),
+            SortOrder::AccessedNewest => Box::new(MetaSorter::newest(Timestamp::Accessed)),
+            SortOrder::AccessedOldest => Box::new(MetaSorter::oldest(Timestamp::Accessed)),
+            SortOrder::ShortestPath => Box::new(Depth::shortest()),
+            SortOrder::DeepestPath => Box::new(Depth::deepest()),
+            SortOrder::Alphabetical => Box::new(Alphabetical),
+            SortOrder::Prefer(dir) => Box::new(Prefer::new(dir)),
+            SortOrder::Avoid(dir) => Box::new(Avoid::new(dir)),
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = ParseSortOrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(dir) = s.strip_prefix("prefer:").filter(|dir| !dir.is_empty()) {
+            return Ok(SortOrder::Prefer(dir.into()));
+        }
+
+        if let Some(dir) = s.strip_prefix("avoid:").filter(|dir| !dir.is_empty()) {
+            return Ok(SortOrder::Avoid(dir.into()));
+        }
+
+        match s.to_lowercase().as_ref() {
+            "d" | "descriptive" => Ok(SortOrder::Descriptive),
+            "o" | "oldest" | "created-oldest" => Ok(SortOrder::Oldest),
+            "n" | "newest" | "created-newest" => Ok(SortOrder::Newest),
+            "modified-newest" => Ok(SortOrder::ModifiedNewest),
+            "modified-oldest" => Ok(SortOrder::ModifiedOldest),
+            "accessed-newest" => Ok(SortOrder::AccessedNewest),
+            "accessed-oldest" => Ok(SortOrder::AccessedOldest),
+            "shortest-path" => Ok(SortOrder::ShortestPath),
+            "deepest-path" => Ok(SortOrder::DeepestPath),
+            "alphabetical" => Ok(SortOrder::Alphabetical),
+            _ => Err(ParseSortOrderError(s.into())),
+        }
+    }
+}
+
+impl Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortOrder::Descriptive => f.write_str("descriptive"),
+            SortOrder::Newest => f.write_str("newest"),
+            SortOrder::Oldest => f.write_str("oldest"),
+            SortOrder::ModifiedNewest => f.write_str("modified-newest"),
+            SortOrder::ModifiedOldest => f.write_str("modified-oldest"),
+            SortOrder::AccessedNewest => f.write_str("accessed-newest"),
+            SortOrder::AccessedOldest => f.write_str("accessed-oldest"),
+            SortOrder::ShortestPath => f.write_str("shortest-path"),
+            SortOrder::DeepestPath => f.write_str("deepest-path"),
+            SortOrder::Alphabetical => f.write_str("alphabetical"),
+            SortOrder::Prefer(dir) => write!(f, "prefer:{}", dir.display()),
+            SortOrder::Avoid(dir) => write!(f, "avoid:{}", dir.display()),
+        }
+    }
+}
+
+/// The error returned when a sort order can't be parsed.
+#[derive(Clone, Debug)]
+pub struct ParseSortOrderError(String);
+
+impl Display for ParseSortOrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} is not a valid sort order\nTry one of descriptive, oldest, newest, \
+            modified-oldest, modified-newest, accessed-oldest, accessed-newest, shortest-path, \
+            deepest-path, alphabetical, prefer:<path>, avoid:<path>",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseSortOrderError {}
+
+/// Build a strategy applying each sort order in turn. With no sort orders, the most descriptive
+/// path is preferred.
+pub fn get_sorter(sort: &[SortOrder]) -> Strategy {
+    get_sorter_with(sort, &PathRanker::new())
+}
+
+/// As [`get_sorter`], but the descriptive order ranks paths using the given ranker, e.g. one with
+/// words of your own added to its dictionary.
+pub fn get_sorter_with(sort: &[SortOrder], ranker: &PathRanker) -> Strategy {
+    if sort.is_empty() {
+        return get_sorter_with(&[SortOrder::default()], ranker);
+    }
+
+    sort.iter().fold(Strategy::new(), |strategy, order| {
+        strategy.then_boxed(order.rule(ranker))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        meta::{Meta, Metacache},
+        rank::PathRanker,
+    };
+
+    use super::{Avoid, MetaSorter, PathSorter, Prefer, SortOrder, Strategy, Timestamp};
+
+    #[test]
+    fn later_rules_break_ties_left_by_earlier_rules() {
+        let strategy = Strategy::new()
+            .then(Prefer::new("originals"))
+            .then(PathRanker::new());
+        let mut paths: Vec<PathBuf> = vec![
+            "inbox/hello world.jpg".into(),
+            "originals/1234.jpg".into(),
+            "originals/hello world.jpg".into(),
+        ];
+
+        strategy.sort(&mut paths, &Metacache::new());
+
+        assert_eq!(
+            paths,
+            [
+                "originals/hello world.jpg",
+                "originals/1234.jpg",
+                "inbox/hello world.jpg"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn explanations_stop_at_the_deciding_rule() {
+        let strategy = Strategy::new()
+            .then(Prefer::new("originals"))
+            .then(PathRanker::new())
+            .then(MetaSorter::oldest(Timestamp::Created));
+        let reasons = PathSorter::explain(
+            &strategy,
+            Path::new("originals/hello world.jpg"),
+            Path::new("originals/1234.jpg"),
+            &Metacache::new(),
+        );
+
+        let rules: Vec<_> = reasons.iter().map(|reason| reason.rule.as_str()).collect();
+        assert_eq!(rules, ["prefer:originals", "descriptive"]);
+        assert!(reasons[0].ordering.is_eq());
+        assert!(reasons[1].ordering.is_lt());
+    }
+
+    #[test]
+    fn avoided_directories_lose_to_everything_else() {
+        let strategy = Strategy::new()
+            .then(Prefer::new("originals"))
+            .then(Prefer::new("albums"))
+            .then(Avoid::new("inbox"))
+            .then(PathRanker::new());
+        let mut paths: Vec<PathBuf> = vec![
+            "inbox/hello world.jpg".into(),
+            "misc/1234.jpg".into(),
+            "albums/1234.jpg".into(),
+            "originals/1234.jpg".into(),
+        ];
+
+        strategy.sort(&mut paths, &Metacache::new());
+
+        assert_eq!(
+            paths,
+            [
+                "originals/1234.jpg",
+                "albums/1234.jpg",
+                "misc/1234.jpg",
+                "inbox/hello world.jpg"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn files_missing_a_timestamp_lose_in_either_direction() {
+        let meta = |modified| Meta {
+            created: None,
+            modified,
+            accessed: None,
+            len: 0,
+        };
+        let epoch = SystemTime::UNIX_EPOCH;
+        let cache: Metacache = [
+            ("a".into(), meta(None)),
+            ("b".into(), meta(Some(epoch + Duration::from_secs(60)))),
+            ("c".into(), meta(Some(epoch))),
+            ("d".into(), meta(None)),
+        ]
+        .into_iter()
+        .collect();
+        let mut paths: Vec<PathBuf> = ["d", "c", "b", "a"].map(PathBuf::from).into();
+
+        MetaSorter::newest(Timestamp::Modified).sort(&mut paths, &cache);
+        assert_eq!(paths, ["b", "c", "a", "d"].map(PathBuf::from));
+
+        MetaSorter::oldest(Timestamp::Modified).sort(&mut paths, &cache);
+        assert_eq!(paths, ["c", "b", "a", "d"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn prefer_parses_with_a_path() {
+        let order: SortOrder = "prefer:/photos/originals".parse().unwrap();
+        assert!(matches!(order, SortOrder::Prefer(dir) if dir == Path::new("/photos/originals")));
+        assert!("prefer:".parse::<SortOrder>().is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use hashbrown::HashMap;
+
+use crate::scan::Scan;
+
+/// Figures gathered while scanning.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// The number of files examined, once ignored and filtered files were set aside.
+    pub files: usize,
+
+    /// The combined length of the files examined.
+    pub bytes: u64,
+
+    /// The number of files sharing their length with another, which had to be hashed.
+    pub candidates: usize,
+
+    /// The combined length of the files which had to be hashed.
+    pub candidate_bytes: u64,
+
+    /// Time spent walking the file system.
+    pub walk_time: Duration,
+
+    /// Time spent hashing, including any search for similar or archived files.
+    pub hash_time: Duration,
+}
+
+/// An overview of the duplicates found by a scan.
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    /// The number of groups of identical files.
+    pub groups: usize,
+
+    /// The number of files to be removed.
+    pub duplicates: usize,
+
+    /// The space to be reclaimed by removing them.
+    pub reclaimable: u64,
+
+    /// The groups reclaiming the most space, largest first, as indices into [`Scan::groups`].
+    pub largest: Vec<usize>,
+
+    /// The directories holding the most reclaimable space, largest first, with the number of
+    /// duplicates in each and their combined length.
+    pub directories: Vec<(PathBuf, usize, u64)>,
+
+    /// The number of groups having each number of files.
+    pub distribution: BTreeMap<usize, usize>,
+}
+
+impl Summary {
+    /// Summarize a scan, listing no more than `top` groups and directories.
+    pub fn new(scan: &Scan, top: usize) -> Self {
+        let len = |path: &Path| scan.cache.get(path).map(|x| x.len).unwrap_or_default();
+        let reclaimable = |idx: usize| {
+            let group = &scan.groups[idx];
+            group.duplicates().iter().map(|path| len(path)).sum::<u64>()
+        };
+
+        let mut summary = Summary {
+            groups: scan.groups.len(),
+            ..Default::default()
+        };
+
+        let mut directories: HashMap<&Path, (usize, u64)> = HashMap::new();
+        for group in &scan.groups {
+            *summary.distribution.entry(group.files.len()).or_default() += 1;
+            for path in group.duplicates() {
+                summary.duplicates += 1;
+                summary.reclaimable += len(path);
+
+                let dir = directories
+                    .entry(path.parent().unwrap_or(path))
+                    .or_default();
+                dir.0 += 1;
+                dir.1 += len(path);
+            }
+        }
+
+        let mut largest: Vec<_> = (0..scan.groups.len()).collect();
+        largest.sort_by_key(|&idx| (std::cmp::Reverse(reclaimable(idx)), idx));
+        largest.truncate(top);
+        summary.largest = largest;
+
+        let mut directories: Vec<_> = directories
+            .into_iter()
+            .map(|(dir, (count, bytes))| (dir.to_owned(), count, bytes))
+            .collect();
+        directories.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        directories.truncate(top);
+        summary.directories = directories;
+
+        summary
+    }
+}
+
+/// Two directories holding copies of the same files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharedDirs {
+    /// The directories, in order of their paths.
+    pub dirs: [PathBuf; 2],
+
+    /// The number of distinct files, by content, found in both directories.
+    pub files: usize,
+
+    /// The space to be reclaimed by removing the copies from one directory or the other.
+    pub bytes: u64,
+}
+
+impl SharedDirs {
+    /// Pair up the directories holding each group's files, for every group in the scan. Pairs
+    /// sharing the most data come first.
+    pub fn find(scan: &Scan) -> Vec<Self> {
+        let mut pairs: HashMap<[&Path; 2], (usize, u64)> = HashMap::new();
+        for group in &scan.groups {
+            let len = group
+                .files
+                .first()
+                .and_then(|path| scan.cache.get(path))
+                .map(|meta| meta.len)
+                .unwrap_or_default();
+
+            let mut dirs: Vec<_> = group
+                .files
+                .iter()
+                .map(|path| path.parent().unwrap_or(path))
+                .collect();
+            dirs.sort();
+            dirs.dedup();
+
+            for (idx, &a) in dirs.iter().enumerate() {
+                for &b in &dirs[idx + 1..] {
+                    let pair = pairs.entry([a, b]).or_default();
+                    pair.0 += 1;
+                    pair.1 += len;
+                }
+            }
+        }
+
+        let mut pairs: Vec<_> = pairs
+            .into_iter()
+            .map(|([a, b], (files, bytes))| SharedDirs {
+                dirs: [a.to_owned(), b.to_owned()],
+                files,
+                bytes,
+            })
+            .collect();
+
+        pairs.sort_by(|a, b| {
+            (b.bytes, b.files)
+                .cmp(&(a.bytes, a.files))
+                .then_with(|| a.dirs.cmp(&b.dirs))
+        });
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scan::Scanner;
+
+    use std::path::Path;
+
+    use super::{SharedDirs, Summary};
+
+    #[test]
+    fn summary_accounts_for_every_duplicate() {
+        let scan = Scanner::new("./resource/test-folder").scan().unwrap();
+        let summary = Summary::new(&scan, 1);
+
+        let duplicates: usize = scan.groups.iter().map(|x| x.duplicates().len()).sum();
+        assert_eq!(summary.groups, scan.groups.len());
+        assert_eq!(summary.duplicates, duplicates);
+        assert_eq!(
+            summary.distribution.values().sum::<usize>(),
+            scan.groups.len()
+        );
+        assert_eq!(summary.largest.len(), 1);
+        assert!(scan.stats.files >= scan.stats.candidates);
+        assert!(scan.stats.candidates >= duplicates + summary.groups);
+    }
+
+    #[test]
+    fn directories_sharing_a_group_are_paired() {
+        let scan = Scanner::new("./resource/test-folder").scan().unwrap();
+        let pairs = SharedDirs::find(&scan);
+
+        let [pair] = &pairs[..] else {
+            panic!("expected exactly one pair");
+        };
+        assert_eq!(
+            pair.dirs,
+            [
+                Path::new("./resource/test-folder"),
+                Path::new("./resource/test-folder/subfolder")
+            ]
+        );
+        assert_eq!(pair.files, 1);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Files larger than this are assumed not to be documents.
+const MAX_TEXT_LEN: u64 = 16 << 20;
+
+/// A file with a NUL byte this close to its start is assumed to be binary, and read no further.
+const SNIFF_LEN: usize = 8 << 10;
+
+/// The number of consecutive words hashed together by SimHash.
+const SHINGLE_LEN: usize = 3;
+
+/// A way of hashing the contents of a text file, such that nearly identical files have hashes
+/// differing in only a few bits, if at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextHash {
+    /// Hashes each run of three words and combines the results, so that files sharing most of
+    /// their wording have hashes differing in only a few bits. Case and punctuation are ignored.
+    #[default]
+    SimHash,
+
+    /// Hashes the whole file once whitespace has been normalized, so that files differing only
+    /// in line endings, indentation or trailing whitespace have identical hashes.
+    Normalized,
+}
+
+impl TextHash {
+    /// Hash a text file, or return `None` if it appears to be binary, is too large to be a
+    /// document, or has no words in it.
+    pub fn hash(self, path: &Path) -> Option<u64> {
+        let text = read_text(path).ok()??;
+        match self {
+            TextHash::SimHash => sim_hash(&text),
+            TextHash::Normalized => normalized_hash(&text),
+        }
+    }
+}
+
+/// A text file's contents with each run of whitespace replaced by a single space, and none at
+/// either end, or `None` if it appears not to be a document. Files with the same normalized
+/// contents have the same [`TextHash::Normalized`] hash.
+pub(crate) fn normalized_text(path: &Path) -> Option<String> {
+    let text = read_text(path).ok()??;
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn read_text(path: &Path) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() > MAX_TEXT_LEN {
+        return Ok(None);
+    }
+
+    let mut bytes = Vec::new();
+    (&mut file).take(SNIFF_LEN as u64).read_to_end(&mut bytes)?;
+    if bytes.contains(&0) {
+        return Ok(None);
+    }
+
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+fn sim_hash(text: &str) -> Option<u64> {
+    let words: Vec<_> = text
+        .split(|u: char| !u.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    // Each shingle votes on each bit of the result.
+    let mut votes = [0i32; 64];
+    for shingle in words.windows(SHINGLE_LEN.min(words.len())) {
+        let hash = shingle.iter().fold(FNV_OFFSET, |hash, word| {
+            fnv(fnv(hash, word.as_bytes()), b" ")
+        });
+        let hash = mix(hash);
+
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            *vote += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+
+    Some(
+        votes
+            .iter()
+            .enumerate()
+            .filter(|(_, vote)| **vote > 0)
+            .fold(0, |hash, (bit, _)| hash | 1 << bit),
+    )
+}
+
+fn normalized_hash(text: &str) -> Option<u64> {
+    let mut words = text.split_whitespace().peekable();
+    words.peek()?;
+
+    let hash = words.fold(FNV_OFFSET, |hash, word| {
+        fnv(fnv(hash, word.as_bytes()), b" ")
+    });
+    Some(mix(hash))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a, which is stable from one run to the next, unlike the standard library's hasher.
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Spread the bits of an FNV hash, whose high bits depend too little on its input for SimHash's
+/// purposes.
+fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ hash >> 31
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalized_hash, sim_hash};
+
+    fn document(seed: u32, len: usize) -> String {
+        static WORDS: &[&str] = &[
+            "the",
+            "quarterly",
+            "report",
+            "shows",
+            "revenue",
+            "growth",
+            "in",
+            "every",
+            "region",
+            "while",
+            "costs",
+            "remained",
+            "flat",
+            "and",
+            "our",
+            "customers",
+            "renewed",
+            "at",
+            "record",
+            "rates",
+            "despite",
+            "a",
+            "difficult",
+            "market",
+        ];
+
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                WORDS[state as usize % WORDS.len()]
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn whitespace_does_not_change_normalized_hash() {
+        let a = normalized_hash("hello  world\r\n\tgoodbye\r\n");
+        let b = normalized_hash("hello world\ngoodbye");
+        let c = normalized_hash("hello world goodbye!");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(normalized_hash(" \n "), None);
+    }
+
+    #[test]
+    fn nearly_identical_text_has_close_sim_hashes() {
+        let original = document(7, 400);
+        let edited = format!(
+            "DRAFT: {}\n-- sent from my phone",
+            original.replace("the ", "The ")
+        );
+        let unrelated = document(11, 400);
+
+        let a = sim_hash(&original).unwrap();
+        let b = sim_hash(&edited).unwrap();
+        let c = sim_hash(&unrelated).unwrap();
+        assert!((a ^ b).count_ones() <= 6);
+        assert!((a ^ c).count_ones() > 16);
+    }
+}
@@ -0,0 +1,140 @@
+/// A run of characters within a path segment which may form a word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+
+    /// Whether the token is written in a script which does not separate words with spaces, such
+    /// as Chinese or Japanese. Such a token may hold several words.
+    pub unspaced: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Upper,
+    Lower,
+    Digit,
+    Unspaced,
+    Separator,
+}
+
+impl Class {
+    fn of(u: char) -> Self {
+        if is_unspaced(u) {
+            Class::Unspaced
+        } else if u.is_uppercase() {
+            Class::Upper
+        } else if u.is_alphabetic() {
+            // Includes letters from scripts without case.
+            Class::Lower
+        } else if u.is_numeric() {
+            Class::Digit
+        } else {
+            Class::Separator
+        }
+    }
+
+    /// Upper and lower case letters may belong to the same word; nothing else may.
+    fn is_letter(self) -> bool {
+        matches!(self, Class::Upper | Class::Lower)
+    }
+}
+
+/// Split a path segment into tokens.
+///
+/// Tokens are separated by anything other than letters and digits, and are further split
+/// wherever letters meet digits, wherever a lower case letter meets an upper case letter
+/// (`VacationPhoto`), and before the last of a run of capitals followed by a lower case letter
+/// (`HTMLParser`). Runs of Chinese or Japanese characters form tokens of their own.
+pub fn tokenize(segment: &str) -> Vec<Token<'_>> {
+    let chars: Vec<_> = segment
+        .char_indices()
+        .map(|(idx, u)| (idx, Class::of(u)))
+        .collect();
+
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut push = |from: usize, to: usize, class: Class| {
+        tokens.push(Token {
+            text: &segment[from..to],
+            unspaced: class == Class::Unspaced,
+        })
+    };
+
+    for (i, &(idx, class)) in chars.iter().enumerate() {
+        if class == Class::Separator {
+            if let Some(from) = start.take() {
+                push(from, idx, chars[i - 1].1);
+            }
+            continue;
+        }
+
+        let Some(from) = start else {
+            start = Some(idx);
+            continue;
+        };
+
+        let (prev_idx, prev) = chars[i - 1];
+        let split = match (prev, class) {
+            (Class::Lower, Class::Upper) => Some(idx),
+            (Class::Upper, Class::Lower) if i >= 2 && chars[i - 2].1 == Class::Upper => {
+                Some(prev_idx).filter(|&at| at > from)
+            }
+            (a, b) if a != b && !(a.is_letter() && b.is_letter()) => Some(idx),
+            _ => None,
+        };
+
+        if let Some(at) = split {
+            push(from, at, prev);
+            start = Some(at);
+        }
+    }
+
+    if let (Some(from), Some(&(_, class))) = (start, chars.last()) {
+        push(from, segment.len(), class);
+    }
+
+    tokens
+}
+
+fn is_unspaced(u: char) -> bool {
+    matches!(u,
+        '\u{3040}'..='\u{30ff}' // Hiragana, Katakana
+        | '\u{31f0}'..='\u{31ff}' // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4dbf}' // CJK unified ideographs extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK unified ideographs
+        | '\u{f900}'..='\u{faff}' // CJK compatibility ideographs
+        | '\u{ff66}'..='\u{ff9f}' // Halfwidth Katakana
+        | '\u{20000}'..='\u{2fa1f}' // CJK unified ideographs extensions B and later
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize;
+
+    fn words(segment: &str) -> Vec<&str> {
+        tokenize(segment)
+            .into_iter()
+            .map(|token| token.text)
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_case_and_digits() {
+        assert_eq!(
+            words("VacationPhotoBeach2024.jpg"),
+            ["Vacation", "Photo", "Beach", "2024", "jpg"]
+        );
+        assert_eq!(words("HTMLParser_v2"), ["HTML", "Parser", "v", "2"]);
+        assert_eq!(words("hello-world (1)"), ["hello", "world", "1"]);
+    }
+
+    #[test]
+    fn unspaced_scripts_form_their_own_tokens() {
+        let tokens = tokenize("東京タワーTokyo旅行.jpg");
+        let words: Vec<_> = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(words, ["東京タワー", "Tokyo", "旅行", "jpg"]);
+        assert!(tokens[0].unspaced);
+        assert!(!tokens[1].unspaced);
+    }
+}